@@ -1,6 +1,9 @@
 use std::fs;
 
-use dir_cmp::{full::compare_dirs, Options};
+use dir_cmp::{
+    full::compare_dirs, CompareMode, HashAlgorithm, MetadataChecks, NameMatching, Options,
+    SymlinkMode,
+};
 
 fn main() {
     //prepare left dir
@@ -23,13 +26,28 @@ fn main() {
         ignore_left_only: false,
         ignore_right_only: false,
         filter: None,
+        left_filter: None,
+        right_filter: None,
         recursive: false,
+        threads: 1,
+        compare_mode: CompareMode::CONTENT,
+        hash_algorithm: HashAlgorithm::Sha256,
+        digest_cache: None,
+        symlinks: SymlinkMode::Ignore,
+        metadata_checks: MetadataChecks::default(),
+        xattrs: None,
+        hard_links: false,
+        ignore_files: None,
+        max_depth: None,
+        min_depth: 0,
+        one_file_system: false,
+        name_matching: NameMatching::default(),
     };
 
     //compare
-    // --> [DirCompEntry::Both(<left_path>/both.txt, <right_path>/both.txt, FileCompResult::Equal),
-    //      DirCompEntry::Both(<left_path>/left_only.txt),
-    //      DirCompEntry::Both(<right_path>/right_only.txt)]
+    // --> [DirCompEntry::Both("both.txt", FileCompResult::Equal, FileCompDetails { .. }),
+    //      DirCompEntry::Left("left_only.txt"),
+    //      DirCompEntry::Right("right_only.txt")]
     let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
     println!("{:?}", result)
 }
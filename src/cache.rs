@@ -0,0 +1,368 @@
+use log::warn;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Digest, Error, HashAlgorithm};
+
+const CACHE_HEADER: &str = "dir-cmp digest cache v1";
+
+//files changed more recently are not cached
+const RACY_SECONDS: i64 = 2;
+
+//identifies one version of a file, any change of its contents changes at least one component
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    device: u64,
+    inode: u64,
+    size: u64,
+    mtime: (i64, i64),
+    ctime: (i64, i64),
+    algorithm: HashAlgorithm,
+}
+
+impl CacheKey {
+    #[cfg(unix)]
+    fn new(metadata: &Metadata, algorithm: HashAlgorithm) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        Some(CacheKey {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            mtime: (metadata.mtime(), metadata.mtime_nsec()),
+            ctime: (metadata.ctime(), metadata.ctime_nsec()),
+            algorithm,
+        })
+    }
+
+    //without inode numbers files can not be identified reliably
+    #[cfg(not(unix))]
+    fn new(_metadata: &Metadata, _algorithm: HashAlgorithm) -> Option<Self> {
+        None
+    }
+
+    //a file changed within the timestamp granularity may change again without changing its key
+    fn is_racy(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(i64::MAX);
+        now - self.mtime.0.max(self.ctime.0) < RACY_SECONDS
+    }
+
+    fn to_line(self, digest: &Digest) -> String {
+        format!(
+            "{} {} {} {} {} {} {} {} {}",
+            self.device,
+            self.inode,
+            self.size,
+            self.mtime.0,
+            self.mtime.1,
+            self.ctime.0,
+            self.ctime.1,
+            self.algorithm.name(),
+            digest.to_hex()
+        )
+    }
+
+    fn from_line(line: &str) -> Option<(Self, Digest)> {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() != 9 {
+            return None;
+        }
+        let algorithm = HashAlgorithm::from_name(fields[7])?;
+        let key = CacheKey {
+            device: fields[0].parse().ok()?,
+            inode: fields[1].parse().ok()?,
+            size: fields[2].parse().ok()?,
+            mtime: (fields[3].parse().ok()?, fields[4].parse().ok()?),
+            ctime: (fields[5].parse().ok()?, fields[6].parse().ok()?),
+            algorithm,
+        };
+        let digest = Digest::from_hex(algorithm, fields[8])?;
+        Some((key, digest))
+    }
+}
+
+struct CacheEntry {
+    digest: Digest,
+    used: bool,
+}
+
+/// Persistent cache of file digests, stored in a single local file.
+///
+/// Digests are keyed by device, inode, size, modification and change time of a file, so
+/// any change to a file makes its cached digest unreachable. Pass it to
+/// `Options::digest_cache` and call [`DigestCache::save`] after the comparison.
+pub struct DigestCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl DigestCache {
+    /// Loads the cache from `path`, a missing file results in an empty cache.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let cache_error = |source| Error::Cache {
+            path: path.to_path_buf(),
+            source,
+        };
+        let mut entries = HashMap::new();
+
+        match File::open(path) {
+            Ok(file) => {
+                let mut lines = BufReader::new(file).lines();
+                match lines.next().transpose().map_err(cache_error)? {
+                    Some(header) if header == CACHE_HEADER => {}
+                    None => {}
+                    Some(_) => {
+                        return Err(cache_error(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "not a dir-cmp digest cache",
+                        )))
+                    }
+                }
+                for line in lines {
+                    let line = line.map_err(cache_error)?;
+                    match CacheKey::from_line(&line) {
+                        Some((key, digest)) => {
+                            entries.insert(
+                                key,
+                                CacheEntry {
+                                    digest,
+                                    used: false,
+                                },
+                            );
+                        }
+                        None => warn!("skipping malformed line in digest cache {:?}", path),
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(cache_error(err)),
+        }
+
+        Ok(DigestCache {
+            path: path.to_path_buf(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// number of cached digests
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops all digests which were neither looked up nor added since the cache was opened.
+    pub fn prune_unused(&self) {
+        self.entries.lock().unwrap().retain(|_, entry| entry.used);
+    }
+
+    /// Writes the cache back to its file.
+    ///
+    /// The file is replaced atomically, so an interrupted save keeps the previous cache.
+    pub fn save(&self) -> Result<(), Error> {
+        let cache_error = |source| Error::Cache {
+            path: self.path.clone(),
+            source,
+        };
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut writer = BufWriter::new(File::create(&tmp_path).map_err(cache_error)?);
+        writeln!(writer, "{}", CACHE_HEADER).map_err(cache_error)?;
+        for (key, entry) in self.entries.lock().unwrap().iter() {
+            writeln!(writer, "{}", key.to_line(&entry.digest)).map_err(cache_error)?;
+        }
+        writer
+            .into_inner()
+            .map_err(|err| cache_error(err.into_error()))?
+            .sync_all()
+            .map_err(cache_error)?;
+
+        fs::rename(&tmp_path, &self.path).map_err(cache_error)
+    }
+
+    pub(crate) fn get(&self, metadata: &Metadata, algorithm: HashAlgorithm) -> Option<Digest> {
+        let key = CacheKey::new(metadata, algorithm)?;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&key)?;
+        entry.used = true;
+        Some(entry.digest.clone())
+    }
+
+    //caches the digest if the file did not change while it was hashed
+    pub(crate) fn insert_unchanged(&self, before: &Metadata, after: &Metadata, digest: &Digest) {
+        let before_key = CacheKey::new(before, digest.algorithm());
+        let after_key = CacheKey::new(after, digest.algorithm());
+        match after_key {
+            Some(key) if before_key == after_key && !key.is_racy() => self.insert(after, digest),
+            _ => {}
+        }
+    }
+
+    fn insert(&self, metadata: &Metadata, digest: &Digest) {
+        if let Some(key) = CacheKey::new(metadata, digest.algorithm()) {
+            self.entries.lock().unwrap().insert(
+                key,
+                CacheEntry {
+                    digest: digest.clone(),
+                    used: true,
+                },
+            );
+        }
+    }
+}
+
+impl fmt::Debug for DigestCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DigestCache")
+            .field("path", &self.path)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests_digest_cache {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::compare::FileComparer;
+    use crate::{CompareMode, FileCompResult, Options};
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("digest_cache_")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn missing_file() {
+        let dir = create_temp_dir();
+        let cache = DigestCache::open(&dir.path().join("cache")).unwrap();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn not_a_cache() {
+        let dir = create_temp_dir();
+        let cache_path = dir.path().join("cache");
+        fs::write(&cache_path, b"something else\n").unwrap();
+
+        let result = DigestCache::open(&cache_path);
+        assert!(matches!(result, Err(Error::Cache { path, .. }) if path == cache_path));
+    }
+
+    #[test]
+    fn save_and_open() {
+        let dir = create_temp_dir();
+        let cache_path = dir.path().join("cache");
+        let file = dir.path().join("file");
+        fs::write(&file, b"test").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        let digest = Digest::new(HashAlgorithm::Sha256, vec![1, 2, 3]);
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        cache.insert(&metadata, &digest);
+        cache.save().unwrap();
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&metadata, HashAlgorithm::Sha256), Some(digest));
+        assert_eq!(cache.get(&metadata, HashAlgorithm::Blake3), None);
+    }
+
+    #[test]
+    fn changed_file_is_rehashed() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(&left_file, b"test").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(&right_file, b"test").unwrap();
+
+        //poison the cache, a hit has to return the cached digest
+        let cache = Arc::new(DigestCache::open(&dir.path().join("cache")).unwrap());
+        let fake_digest = Digest::new(HashAlgorithm::Sha256, vec![0]);
+        cache.insert(&fs::metadata(&left_file).unwrap(), &fake_digest);
+
+        let options = Options {
+            compare_mode: CompareMode {
+                hash: true,
+                ..CompareMode::NAMES
+            },
+            digest_cache: Some(Arc::clone(&cache)),
+            ..Default::default()
+        };
+        let comparer = FileComparer::new(&options);
+        let (result, details) = comparer.compare(&left_file, &right_file).unwrap();
+        assert_eq!(result, FileCompResult::Different(crate::Check::Hash));
+        assert_eq!(details.digests.unwrap().0, fake_digest);
+
+        //any change of the file invalidates the cached digest
+        File::options()
+            .write(true)
+            .open(&left_file)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let (result, _) = comparer.compare(&left_file, &right_file).unwrap();
+        assert_eq!(result, FileCompResult::Equal);
+    }
+
+    #[test]
+    fn racy_files_are_not_cached() {
+        let dir = create_temp_dir();
+        let file = dir.path().join("file");
+        fs::write(&file, b"test").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        let digest = Digest::new(HashAlgorithm::Sha256, vec![1, 2, 3]);
+
+        let cache = DigestCache::open(&dir.path().join("cache")).unwrap();
+        cache.insert_unchanged(&metadata, &metadata, &digest);
+        assert!(cache.is_empty());
+
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let old_metadata = fs::metadata(&file).unwrap();
+        cache.insert_unchanged(&old_metadata, &old_metadata, &digest);
+        //the change time is still recent
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn prune_unused() {
+        let dir = create_temp_dir();
+        let cache_path = dir.path().join("cache");
+        let file = dir.path().join("file");
+        fs::write(&file, b"test").unwrap();
+        let digest = Digest::new(HashAlgorithm::Sha256, vec![1, 2, 3]);
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        cache.insert(&fs::metadata(&file).unwrap(), &digest);
+        cache.save().unwrap();
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        cache.prune_unused();
+        assert!(cache.is_empty());
+    }
+}
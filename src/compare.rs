@@ -0,0 +1,531 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::hardlink::HardLinkDiff;
+use crate::hash::{file_digest, Digest, HashAlgorithm};
+use crate::metadata::{metadata_diffs, MetadataChecks, MetadataDiff};
+use crate::special::SpecialFiles;
+use crate::xattrs::{xattr_diffs, XattrChecks, XattrDiff};
+use crate::{DigestCache, Error, Options, SymlinkKind};
+
+/// The checks used to decide whether two files with the same name are equal.
+///
+/// Checks are combinable and run from the cheapest to the most expensive one. The first
+/// check that fails decides the result. Without any check files are only matched by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompareMode {
+    /// compare the file size
+    pub size: bool,
+    /// compare the modification time
+    pub mtime: bool,
+    /// compare the contents byte by byte
+    pub content: bool,
+    /// compare a digest of the contents
+    pub hash: bool,
+}
+
+impl CompareMode {
+    /// only check that both sides have a file with the same name
+    pub const NAMES: CompareMode = CompareMode {
+        size: false,
+        mtime: false,
+        content: false,
+        hash: false,
+    };
+
+    /// compare sizes only, like rsync's `--size-only`
+    pub const SIZE_ONLY: CompareMode = CompareMode {
+        size: true,
+        ..CompareMode::NAMES
+    };
+
+    /// compare sizes and modification times, like rsync's default quick check
+    pub const QUICK: CompareMode = CompareMode {
+        size: true,
+        mtime: true,
+        ..CompareMode::NAMES
+    };
+
+    /// compare sizes and full contents
+    pub const CONTENT: CompareMode = CompareMode {
+        size: true,
+        content: true,
+        ..CompareMode::NAMES
+    };
+
+    /// compare sizes and digests, like rsync's `--checksum`
+    pub const CHECKSUM: CompareMode = CompareMode {
+        size: true,
+        hash: true,
+        ..CompareMode::NAMES
+    };
+
+    fn needs_metadata(&self) -> bool {
+        self.size || self.mtime
+    }
+}
+
+impl Default for CompareMode {
+    fn default() -> Self {
+        CompareMode::CONTENT
+    }
+}
+
+/// The check which established that two files differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Check {
+    Size,
+    Mtime,
+    Content,
+    Hash,
+    /// two symlinks point to different targets
+    SymlinkTarget,
+    /// two device nodes have different device numbers
+    Device,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FileCompResult {
+    Equal,
+    Different(Check),
+}
+
+//size of the blocks in which file contents are compared
+pub(crate) const COMPARE_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Additional information gathered while comparing two files.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileCompDetails {
+    /// digests of the left and right file, if they were computed by the hash check
+    pub digests: Option<(Digest, Digest)>,
+    /// how the left entry was reached, if it is a symlink
+    pub left_symlink: Option<SymlinkKind>,
+    /// how the right entry was reached, if it is a symlink
+    pub right_symlink: Option<SymlinkKind>,
+    /// metadata attributes which differ, if `Options::metadata_checks` are enabled
+    pub metadata: Vec<MetadataDiff>,
+    /// extended attributes which differ, if `Options::xattrs` are compared
+    pub xattrs: Vec<XattrDiff>,
+    /// hard link structure, if `Options::hard_links` are tracked and it differs
+    pub hard_links: Option<HardLinkDiff>,
+    /// kind and device numbers, if both entries are special files
+    pub special: Option<SpecialFiles>,
+}
+
+impl FileCompDetails {
+    //true if the details report differences besides the `FileCompResult`
+    pub(crate) fn has_differences(&self) -> bool {
+        !self.metadata.is_empty() || !self.xattrs.is_empty() || self.hard_links.is_some()
+    }
+}
+
+//compares pairs of files according to the options, shared by all worker threads
+#[derive(Debug, Clone)]
+pub(crate) struct FileComparer {
+    mode: CompareMode,
+    hash_algorithm: HashAlgorithm,
+    digest_cache: Option<Arc<DigestCache>>,
+    metadata_checks: MetadataChecks,
+    xattrs: Option<XattrChecks>,
+    //set once the results are no longer needed, stops reading files between blocks
+    cancelled: Arc<AtomicBool>,
+}
+
+impl FileComparer {
+    pub(crate) fn new(options: &Options) -> Self {
+        FileComparer {
+            mode: options.compare_mode,
+            hash_algorithm: options.hash_algorithm,
+            digest_cache: options.digest_cache.clone(),
+            metadata_checks: options.metadata_checks,
+            xattrs: options.xattrs.clone(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    //shared by all clones of the comparer
+    pub(crate) fn cancelled(&self) -> &Arc<AtomicBool> {
+        &self.cancelled
+    }
+
+    //true if comparing files does not need to touch them at all
+    pub(crate) fn is_names_only(&self) -> bool {
+        self.mode == CompareMode::NAMES
+            && !self.metadata_checks.is_enabled()
+            && self.xattrs.is_none()
+    }
+
+    pub(crate) fn compare(
+        &self,
+        left_path: &Path,
+        right_path: &Path,
+    ) -> Result<(FileCompResult, FileCompDetails), Error> {
+        let (comp_result, mut details) = self.compare_contents(left_path, right_path)?;
+
+        //metadata and attributes are compared no matter whether the contents are equal
+        self.compare_attributes(left_path, right_path, true, &mut details)?;
+        Ok((comp_result, details))
+    }
+
+    //adds the differing attributes of any two entries to the details, symlinks themselves
+    //are compared unless `follow` is set, extended attributes are only read through them
+    pub(crate) fn compare_attributes(
+        &self,
+        left_path: &Path,
+        right_path: &Path,
+        follow: bool,
+        details: &mut FileCompDetails,
+    ) -> Result<(), Error> {
+        if self.metadata_checks.is_enabled() {
+            let read = if follow {
+                read_metadata
+            } else {
+                read_symlink_metadata
+            };
+            details.metadata = metadata_diffs(
+                &self.metadata_checks,
+                left_path,
+                &read(left_path)?,
+                right_path,
+                &read(right_path)?,
+            )?;
+        }
+        if let (Some(xattrs), true) = (&self.xattrs, follow) {
+            details.xattrs = xattr_diffs(xattrs, left_path, right_path)?;
+        }
+        Ok(())
+    }
+
+    fn compare_contents(
+        &self,
+        left_path: &Path,
+        right_path: &Path,
+    ) -> Result<(FileCompResult, FileCompDetails), Error> {
+        let mode = self.mode;
+        let mut details = FileCompDetails::default();
+
+        if mode.needs_metadata() {
+            let left_metadata = read_metadata(left_path)?;
+            let right_metadata = read_metadata(right_path)?;
+
+            if mode.size && left_metadata.len() != right_metadata.len() {
+                return Ok((FileCompResult::Different(Check::Size), details));
+            }
+            if mode.mtime
+                && modified(&left_metadata, left_path)? != modified(&right_metadata, right_path)?
+            {
+                return Ok((FileCompResult::Different(Check::Mtime), details));
+            }
+        }
+
+        if mode.content && !equal_contents(left_path, right_path, &self.cancelled)? {
+            return Ok((FileCompResult::Different(Check::Content), details));
+        }
+
+        if mode.hash {
+            let left_digest = self.digest(left_path)?;
+            let right_digest = self.digest(right_path)?;
+            let equal = left_digest == right_digest;
+            details.digests = Some((left_digest, right_digest));
+            if !equal {
+                return Ok((FileCompResult::Different(Check::Hash), details));
+            }
+        }
+
+        Ok((FileCompResult::Equal, details))
+    }
+
+    //looks up the digest in the cache before hashing the file
+    fn digest(&self, path: &Path) -> Result<Digest, Error> {
+        let cache = match &self.digest_cache {
+            Some(cache) => cache,
+            None => return file_digest(path, self.hash_algorithm, &self.cancelled),
+        };
+
+        let before = read_metadata(path)?;
+        if let Some(digest) = cache.get(&before, self.hash_algorithm) {
+            return Ok(digest);
+        }
+        let digest = file_digest(path, self.hash_algorithm, &self.cancelled)?;
+        let after = read_metadata(path)?;
+        cache.insert_unchanged(&before, &after, &digest);
+        Ok(digest)
+    }
+}
+
+pub(crate) fn read_error(path: &Path) -> impl FnOnce(io::Error) -> Error {
+    let path = path.to_path_buf();
+    move |source| Error::ReadFile { path, source }
+}
+
+fn read_metadata(path: &Path) -> Result<Metadata, Error> {
+    fs::metadata(path).map_err(read_error(path))
+}
+
+fn read_symlink_metadata(path: &Path) -> Result<Metadata, Error> {
+    fs::symlink_metadata(path).map_err(read_error(path))
+}
+
+fn modified(metadata: &Metadata, path: &Path) -> Result<std::time::SystemTime, Error> {
+    metadata.modified().map_err(read_error(path))
+}
+
+fn equal_contents(
+    left_path: &Path,
+    right_path: &Path,
+    cancelled: &AtomicBool,
+) -> Result<bool, Error> {
+    //files of different size can not be equal
+    if read_metadata(left_path)?.len() != read_metadata(right_path)?.len() {
+        return Ok(false);
+    }
+
+    let mut left_file = File::open(left_path).map_err(read_error(left_path))?;
+    let mut right_file = File::open(right_path).map_err(read_error(right_path))?;
+    let mut left_block = vec![0; COMPARE_BLOCK_SIZE];
+    let mut right_block = vec![0; COMPARE_BLOCK_SIZE];
+
+    loop {
+        let left_read = read_block(&mut left_file, &mut left_block, cancelled)
+            .map_err(read_error(left_path))?;
+        let right_read = read_block(&mut right_file, &mut right_block, cancelled)
+            .map_err(read_error(right_path))?;
+
+        if left_block[..left_read] != right_block[..right_read] {
+            return Ok(false);
+        }
+        //end of both files
+        if left_read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+//fills the block as far as possible, only returns less than its length at the end of the file,
+//fails without reading once the comparison is cancelled
+pub(crate) fn read_block(
+    file: &mut File,
+    block: &mut [u8],
+    cancelled: &AtomicBool,
+) -> io::Result<usize> {
+    if cancelled.load(Ordering::Relaxed) {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "comparison cancelled",
+        ));
+    }
+    let mut filled = 0;
+    while filled < block.len() {
+        match file.read(&mut block[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests_file_comparer {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn compare_two_files(
+        left_path: &Path,
+        right_path: &Path,
+        mode: CompareMode,
+    ) -> Result<FileCompResult, Error> {
+        let options = Options {
+            compare_mode: mode,
+            ..Default::default()
+        };
+        let (comp_result, _) = FileComparer::new(&options).compare(left_path, right_path)?;
+        Ok(comp_result)
+    }
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("compare_two_files_")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn equal() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, world!").unwrap();
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::CONTENT).unwrap();
+        assert_eq!(result, FileCompResult::Equal);
+    }
+
+    #[test]
+    fn different_size() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, world!!").unwrap();
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::CONTENT).unwrap();
+        assert_eq!(result, FileCompResult::Different(Check::Size));
+    }
+
+    #[test]
+    fn different_in_last_block() {
+        let dir = create_temp_dir();
+        let mut content = vec![7u8; 3 * COMPARE_BLOCK_SIZE + 11];
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), &content).unwrap();
+        *content.last_mut().unwrap() = 8;
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), &content).unwrap();
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::CONTENT).unwrap();
+        assert_eq!(result, FileCompResult::Different(Check::Content));
+    }
+
+    #[test]
+    fn equal_multiple_blocks() {
+        let dir = create_temp_dir();
+        let content = vec![7u8; 2 * COMPARE_BLOCK_SIZE + 11];
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), &content).unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), &content).unwrap();
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::CONTENT).unwrap();
+        assert_eq!(result, FileCompResult::Equal);
+    }
+
+    #[test]
+    fn missing_file() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::CONTENT);
+        assert!(matches!(result, Err(Error::ReadFile { path, .. }) if path == right_file));
+    }
+
+    #[test]
+    fn cancelled() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, world!").unwrap();
+
+        let comparer = FileComparer::new(&Options {
+            compare_mode: CompareMode::CONTENT,
+            ..Default::default()
+        });
+        comparer.cancelled().store(true, Ordering::Relaxed);
+        let result = comparer.compare(&left_file, &right_file);
+        assert!(matches!(
+            result,
+            Err(Error::ReadFile { source, .. }) if source.kind() == io::ErrorKind::Interrupted
+        ));
+    }
+
+    #[test]
+    fn names_only() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+
+        //files are not even opened
+        let result = compare_two_files(&left_file, &right_file, CompareMode::NAMES).unwrap();
+        assert_eq!(result, FileCompResult::Equal);
+    }
+
+    #[test]
+    fn size_only() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, World!").unwrap();
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::SIZE_ONLY).unwrap();
+        assert_eq!(result, FileCompResult::Equal);
+    }
+
+    #[test]
+    fn mtime() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, world!").unwrap();
+        let older = SystemTime::now() - Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(&right_file)
+            .unwrap()
+            .set_modified(older)
+            .unwrap();
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::QUICK).unwrap();
+        assert_eq!(result, FileCompResult::Different(Check::Mtime));
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::CONTENT).unwrap();
+        assert_eq!(result, FileCompResult::Equal);
+    }
+
+    #[test]
+    fn checksum() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, World!").unwrap();
+        let equal_file = dir.path().join("equal");
+        fs::write(equal_file.as_path(), b"Hello, world!").unwrap();
+
+        let result = compare_two_files(&left_file, &right_file, CompareMode::CHECKSUM).unwrap();
+        assert_eq!(result, FileCompResult::Different(Check::Hash));
+
+        let result = compare_two_files(&left_file, &equal_file, CompareMode::CHECKSUM).unwrap();
+        assert_eq!(result, FileCompResult::Equal);
+    }
+
+    #[test]
+    fn checksum_details() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"test").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"tess").unwrap();
+
+        let options = Options {
+            compare_mode: CompareMode::CHECKSUM,
+            hash_algorithm: HashAlgorithm::Xxh3,
+            ..Default::default()
+        };
+        let (result, details) = FileComparer::new(&options)
+            .compare(&left_file, &right_file)
+            .unwrap();
+        assert_eq!(result, FileCompResult::Different(Check::Hash));
+
+        let (left_digest, right_digest) = details.digests.unwrap();
+        assert_eq!(left_digest.algorithm(), HashAlgorithm::Xxh3);
+        assert_eq!(
+            left_digest.as_bytes(),
+            xxhash_rust::xxh3::xxh3_128(b"test").to_be_bytes()
+        );
+        assert_eq!(
+            right_digest.as_bytes(),
+            xxhash_rust::xxh3::xxh3_128(b"tess").to_be_bytes()
+        );
+    }
+}
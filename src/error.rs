@@ -0,0 +1,55 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    /// one of the compared roots does not exist
+    RootNotFound(PathBuf),
+    /// one of the compared roots exists but is not a directory
+    RootNotDirectory(PathBuf),
+    /// a directory could not be listed
+    ReadDir { path: PathBuf, source: io::Error },
+    /// a file could not be read
+    ReadFile { path: PathBuf, source: io::Error },
+    /// the digest cache could not be read or written
+    Cache { path: PathBuf, source: io::Error },
+    /// a glob pattern of a filter could not be compiled
+    InvalidPattern {
+        pattern: String,
+        source: regex::Error,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RootNotFound(path) => write!(f, "path {:?} does not exist", path),
+            Error::RootNotDirectory(path) => write!(f, "path {:?} is not a directory", path),
+            Error::ReadDir { path, source } => {
+                write!(f, "failed to read directory {:?}: {}", path, source)
+            }
+            Error::ReadFile { path, source } => {
+                write!(f, "failed to read file {:?}: {}", path, source)
+            }
+            Error::Cache { path, source } => {
+                write!(f, "failed to access digest cache {:?}: {}", path, source)
+            }
+            Error::InvalidPattern { pattern, source } => {
+                write!(f, "invalid glob pattern {:?}: {}", pattern, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadDir { source, .. }
+            | Error::ReadFile { source, .. }
+            | Error::Cache { source, .. } => Some(source),
+            Error::InvalidPattern { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
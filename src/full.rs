@@ -1,123 +1,455 @@
-use log::{debug, error, trace};
-use std::path::Path;
-use std::{io, path::PathBuf};
+use log::debug;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::compare::FileComparer;
+use crate::hardlink::{HardLinkRole, HardLinkTracker};
+use crate::parallel::ComparePool;
+use crate::special::compare_specials;
+use crate::walk::{DirWalker, WalkEntry};
 use crate::{
-    compare_two_files, list_files, zip_dir_entries, EitherOrBoth, FileCompResult, Options,
+    check_root, Check, EntryDetails, EntryKind, EntryPath, Error, FileCompDetails, FileCompResult,
+    Options, Roots, Sides, Unexplored, ZipEntry,
 };
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+//most entries are file pairs, boxing their details would only add an allocation per pair
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DirCmpEntry {
-    Both(PathBuf, PathBuf, FileCompResult),
-    Left(PathBuf),
-    Right(PathBuf),
+    Both(EntryPath, FileCompResult, FileCompDetails),
+    /// an entry existing on the left only, with its type
+    Left(EntryPath, EntryDetails),
+    /// an entry existing on the right only, with its type
+    Right(EntryPath, EntryDetails),
+    /// the same name is used for different kinds of entries, e.g. a file and a directory
+    TypeMismatch {
+        path: EntryPath,
+        left_kind: EntryKind,
+        right_kind: EntryKind,
+    },
+    /// a directory which was not descended into, so its contents are unknown, the details
+    /// tell whether it was reached through a followed symlink
+    Unexplored {
+        path: EntryPath,
+        sides: Sides,
+        reason: Unexplored,
+        details: FileCompDetails,
+    },
 }
 
-fn compare_dirs_inner(
-    left_path: &Path,
-    right_path: &Path,
-    left_base: &str,
-    right_base: &str,
-    options: &Options,
-) -> io::Result<Vec<DirCmpEntry>> {
-    trace!("comparing 2 dirs");
-
-    let mut results: Vec<DirCmpEntry> = Vec::new();
-    for dir_entry in zip_dir_entries(
-        &left_path.to_path_buf(),
-        &right_path.to_path_buf(),
-        left_base,
-        right_base,
-        &options.filter,
-    )? {
-        match dir_entry {
-            EitherOrBoth::Both(left_entry, right_entry) => {
-                //handle two files
-                if left_entry.is_file() && right_entry.is_file() {
-                    let comp_result = compare_two_files(&left_entry, &right_entry)?;
-                    if FileCompResult::Equal != comp_result || !options.ignore_equal {
-                        results.push(DirCmpEntry::Both(
-                            left_entry.to_owned(),
-                            right_entry.to_owned(),
-                            comp_result,
-                        ));
-                    }
-                }
+impl DirCmpEntry {
+    pub fn path(&self) -> &EntryPath {
+        match self {
+            DirCmpEntry::Both(path, _, _)
+            | DirCmpEntry::Left(path, _)
+            | DirCmpEntry::Right(path, _)
+            | DirCmpEntry::TypeMismatch { path, .. }
+            | DirCmpEntry::Unexplored { path, .. } => path,
+        }
+    }
+}
 
-                //handle two dirs
-                if left_entry.is_dir() && right_entry.is_dir() {
-                    if options.recursive {
-                        let subtree_results = compare_dirs_inner(
-                            left_entry.as_path(),
-                            right_entry.as_path(),
-                            left_base,
-                            right_base,
-                            options,
-                        )?;
-                        results.extend(subtree_results);
-                    } else {
-                        results.push(DirCmpEntry::Both(
-                            left_entry.to_owned(),
-                            right_entry.to_owned(),
-                            FileCompResult::Equal,
-                        ));
-                    }
+//entries in walk order, file pairs may still be compared by the pool
+enum Pending {
+    Ready(Result<DirCmpEntry, Error>),
+    Compare {
+        job: usize,
+        left: PathBuf,
+        right: PathBuf,
+        //details known before comparing, like symlinks and hard links
+        known: FileCompDetails,
+        hard_link: HardLinkRole,
+    },
+}
+
+//number of entries queued per worker thread ahead of the consumer
+const QUEUE_DEPTH_PER_THREAD: usize = 4;
+
+/// Lazy iterator over the differences of two directory trees, see [`compare_dirs_iter`].
+pub struct CompareDirsIter {
+    roots: Arc<Roots>,
+    walker: DirWalker,
+    ignore_equal: bool,
+    comparer: FileComparer,
+    pool: Option<ComparePool>,
+    queue: VecDeque<Pending>,
+    hard_links: Option<HardLinkTracker>,
+    //results of the first pair of each hard link group, reused for the other pairs
+    hard_link_results: HashMap<PathBuf, (FileCompResult, FileCompDetails)>,
+}
+
+impl CompareDirsIter {
+    //walks ahead until the queue is full, so workers can compare while entries are consumed
+    fn fill_queue(&mut self) {
+        let queue_depth = match &self.pool {
+            Some(pool) => pool.threads() * QUEUE_DEPTH_PER_THREAD,
+            None => 1,
+        };
+
+        while self.queue.len() < queue_depth {
+            let dir_entry = match self.walker.next() {
+                None => break,
+                Some(Ok(dir_entry)) => dir_entry,
+                Some(Err(err)) => {
+                    self.queue.push_back(Pending::Ready(Err(err)));
+                    continue;
                 }
+            };
 
-                //ignore symlinks and mismatches
-            }
-            EitherOrBoth::Left(left_entry) => {
-                if !options.ignore_left_only {
-                    if left_entry.is_dir() {
-                        let entry_list = list_files(&left_entry);
-                        for file_path in entry_list {
-                            results.push(DirCmpEntry::Left(file_path));
+            let pending = match dir_entry {
+                WalkEntry::Files {
+                    left,
+                    right,
+                    left_symlink,
+                    right_symlink,
+                } => {
+                    let mut known = FileCompDetails {
+                        left_symlink,
+                        right_symlink,
+                        ..Default::default()
+                    };
+                    let hard_link = match &mut self.hard_links {
+                        Some(tracker) => match tracker.visit(&left, &right) {
+                            Ok((hard_link, diff)) => {
+                                known.hard_links = diff;
+                                hard_link
+                            }
+                            Err(err) => {
+                                self.queue.push_back(Pending::Ready(Err(err)));
+                                continue;
+                            }
+                        },
+                        None => HardLinkRole::Single,
+                    };
+                    match hard_link {
+                        //the same file is always equal to itself
+                        HardLinkRole::SameInode => {
+                            let path = self.roots.pair_entry(&left, &right);
+                            if self.ignore_equal
+                                && !known.has_differences()
+                                && !path.spellings_differ()
+                            {
+                                continue;
+                            }
+                            Pending::Ready(Ok(DirCmpEntry::Both(
+                                path,
+                                FileCompResult::Equal,
+                                known,
+                            )))
+                        }
+                        //followers are compared once the result of their leader is known
+                        HardLinkRole::Follower(_) => Pending::Compare {
+                            job: 0,
+                            left,
+                            right,
+                            known,
+                            hard_link,
+                        },
+                        _ => {
+                            let job = match &mut self.pool {
+                                Some(pool) => pool.submit(left.clone(), right.clone()),
+                                None => 0,
+                            };
+                            Pending::Compare {
+                                job,
+                                left,
+                                right,
+                                known,
+                                hard_link,
+                            }
                         }
-                        continue;
                     }
-                    if left_entry.is_file() {
-                        results.push(DirCmpEntry::Left(left_entry));
+                }
+                WalkEntry::Dirs {
+                    left,
+                    right,
+                    left_symlink,
+                    right_symlink,
+                    descended,
+                } => {
+                    let mut details = FileCompDetails {
+                        left_symlink,
+                        right_symlink,
+                        ..Default::default()
+                    };
+                    if let Err(err) =
+                        self.comparer
+                            .compare_attributes(&left, &right, true, &mut details)
+                    {
+                        self.queue.push_back(Pending::Ready(Err(err)));
                         continue;
                     }
-                    if left_entry.is_symlink() {
-                        //ignore
+                    //descended dirs are only reported if their attributes or their names
+                    //differ, dirs which are not descended into are always reported
+                    let path = self.roots.pair_entry(&left, &right);
+                    if descended && !details.has_differences() && !path.spellings_differ() {
                         continue;
                     }
+                    Pending::Ready(Ok(DirCmpEntry::Both(path, FileCompResult::Equal, details)))
                 }
-            }
-            EitherOrBoth::Right(right_entry) => {
-                if !options.ignore_right_only {
-                    if right_entry.is_dir() {
-                        let entry_list = list_files(&right_entry);
-                        for file_path in entry_list {
-                            results.push(DirCmpEntry::Right(file_path));
-                        }
+                WalkEntry::Symlinks {
+                    left,
+                    right,
+                    left_symlink,
+                    right_symlink,
+                } => {
+                    let comp_result = if self.comparer.is_names_only()
+                        || left_symlink.target() == right_symlink.target()
+                    {
+                        FileCompResult::Equal
+                    } else {
+                        FileCompResult::Different(Check::SymlinkTarget)
+                    };
+                    let mut details = FileCompDetails {
+                        left_symlink: Some(left_symlink),
+                        right_symlink: Some(right_symlink),
+                        ..Default::default()
+                    };
+                    //the attributes of the links themselves, not of their targets
+                    if let Err(err) =
+                        self.comparer
+                            .compare_attributes(&left, &right, false, &mut details)
+                    {
+                        self.queue.push_back(Pending::Ready(Err(err)));
+                        continue;
+                    }
+                    let path = self.roots.pair_entry(&left, &right);
+                    if comp_result == FileCompResult::Equal
+                        && self.ignore_equal
+                        && !path.spellings_differ()
+                        && !details.has_differences()
+                    {
                         continue;
                     }
-                    if right_entry.is_file() {
-                        results.push(DirCmpEntry::Right(right_entry));
+                    Pending::Ready(Ok(DirCmpEntry::Both(path, comp_result, details)))
+                }
+                WalkEntry::Specials { left, right, kind } => {
+                    let (comp_result, special) = match compare_specials(kind, &left, &right) {
+                        Ok(compared) => compared,
+                        Err(err) => {
+                            self.queue.push_back(Pending::Ready(Err(err)));
+                            continue;
+                        }
+                    };
+                    //names only comparisons do not touch the entries at all
+                    let comp_result = if self.comparer.is_names_only() {
+                        FileCompResult::Equal
+                    } else {
+                        comp_result
+                    };
+                    let mut details = FileCompDetails {
+                        special: Some(special),
+                        ..Default::default()
+                    };
+                    if let Err(err) =
+                        self.comparer
+                            .compare_attributes(&left, &right, true, &mut details)
+                    {
+                        self.queue.push_back(Pending::Ready(Err(err)));
                         continue;
                     }
-                    if right_entry.is_symlink() {
-                        //ignore
+                    let path = self.roots.pair_entry(&left, &right);
+                    if comp_result == FileCompResult::Equal
+                        && self.ignore_equal
+                        && !path.spellings_differ()
+                        && !details.has_differences()
+                    {
                         continue;
                     }
+                    Pending::Ready(Ok(DirCmpEntry::Both(path, comp_result, details)))
                 }
+                WalkEntry::Left {
+                    entry,
+                    kind,
+                    symlink,
+                } => Pending::Ready(
+                    EntryDetails::of(kind, symlink, &entry)
+                        .map(|details| DirCmpEntry::Left(self.roots.left_entry(&entry), details)),
+                ),
+                WalkEntry::Right {
+                    entry,
+                    kind,
+                    symlink,
+                } => Pending::Ready(
+                    EntryDetails::of(kind, symlink, &entry)
+                        .map(|details| DirCmpEntry::Right(self.roots.right_entry(&entry), details)),
+                ),
+                WalkEntry::TypeMismatch {
+                    left,
+                    right,
+                    left_kind,
+                    right_kind,
+                } => Pending::Ready(Ok(DirCmpEntry::TypeMismatch {
+                    path: self.roots.pair_entry(&left, &right),
+                    left_kind,
+                    right_kind,
+                })),
+                //the contents are unknown, so they are reported even if equal entries are not
+                WalkEntry::Unexplored {
+                    entry,
+                    reason,
+                    left_symlink,
+                    right_symlink,
+                } => {
+                    let (path, sides) = match entry {
+                        ZipEntry::Both(left, right) => {
+                            (self.roots.pair_entry(&left, &right), Sides::Both)
+                        }
+                        ZipEntry::Left(left) => (self.roots.left_entry(&left), Sides::Left),
+                        ZipEntry::Right(right) => (self.roots.right_entry(&right), Sides::Right),
+                    };
+                    Pending::Ready(Ok(DirCmpEntry::Unexplored {
+                        path,
+                        sides,
+                        reason,
+                        details: FileCompDetails {
+                            left_symlink,
+                            right_symlink,
+                            ..Default::default()
+                        },
+                    }))
+                }
+            };
+            self.queue.push_back(pending);
+        }
+    }
+}
+
+impl Iterator for CompareDirsIter {
+    type Item = Result<DirCmpEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.fill_queue();
+
+            let (job, left_entry, right_entry, known, hard_link) = match self.queue.pop_front()? {
+                Pending::Ready(entry) => return Some(entry),
+                Pending::Compare {
+                    job,
+                    left,
+                    right,
+                    known,
+                    hard_link,
+                } => (job, left, right, known, hard_link),
+            };
+
+            let comp_result = match (&hard_link, &mut self.pool) {
+                (HardLinkRole::Follower(leader), _) => match self.hard_link_results.get(leader) {
+                    Some(leader_result) => Ok(leader_result.clone()),
+                    //the leader could not be compared
+                    None => self.comparer.compare(&left_entry, &right_entry),
+                },
+                (_, Some(pool)) => pool.wait(job),
+                (_, None) => self.comparer.compare(&left_entry, &right_entry),
+            };
+            let (comp_result, mut details) = match comp_result {
+                Ok(comp_result) => comp_result,
+                Err(err) => return Some(Err(err)),
+            };
+            if let HardLinkRole::Leader(leader) = hard_link {
+                self.hard_link_results
+                    .insert(leader, (comp_result.clone(), details.clone()));
+            }
+            details.left_symlink = known.left_symlink;
+            details.right_symlink = known.right_symlink;
+            details.hard_links = known.hard_links;
+            let path = self.roots.pair_entry(&left_entry, &right_entry);
+            //names matching despite different spellings are reported like metadata differences
+            if FileCompResult::Equal != comp_result
+                || details.has_differences()
+                || path.spellings_differ()
+                || !self.ignore_equal
+            {
+                return Some(Ok(DirCmpEntry::Both(path, comp_result, details)));
             }
         }
     }
-    Ok(results)
+}
+
+/// Compares two directories lazily.
+///
+/// Files with the same name are compared with the checks of `options.compare_mode`.
+/// Entries are yielded depth-first as soon as they are known. Dropping the iterator
+/// stops the comparison. If `options.threads` is greater than one, file contents are
+/// compared by that many worker threads while the walk continues, but entries are still
+/// yielded in walk order.
+pub fn compare_dirs_iter(
+    left_path: &Path,
+    right_path: &Path,
+    options: Options,
+) -> Result<CompareDirsIter, Error> {
+    debug!(
+        "starting to compare for {:?} vs {:?}",
+        left_path, right_path
+    );
+
+    check_root(left_path)?;
+    check_root(right_path)?;
+
+    let ignore_equal = options.ignore_equal;
+    let comparer = FileComparer::new(&options);
+    let pool = if options.threads > 1 && !comparer.is_names_only() {
+        Some(ComparePool::new(options.threads, comparer.clone()))
+    } else {
+        None
+    };
+    let hard_links = if options.hard_links {
+        Some(HardLinkTracker::new(left_path, right_path))
+    } else {
+        None
+    };
+    let walker = DirWalker::new(left_path, right_path, options)?;
+
+    Ok(CompareDirsIter {
+        roots: Arc::new(Roots {
+            left: left_path.to_path_buf(),
+            right: right_path.to_path_buf(),
+        }),
+        walker,
+        ignore_equal,
+        comparer,
+        pool,
+        queue: VecDeque::new(),
+        hard_links,
+        hard_link_results: HashMap::new(),
+    })
+}
+
+pub fn compare_dirs(
+    left_path: &Path,
+    right_path: &Path,
+    options: Options,
+) -> Result<Vec<DirCmpEntry>, Error> {
+    compare_dirs_iter(left_path, right_path, options)?.collect()
 }
 
 #[cfg(test)]
-mod tests_compare_dirs_inner {
+mod tests_compare_dirs {
     use super::*;
+    use crate::{CompareMode, FilterSet, SymlinkKind, SymlinkMode};
     use std::fs;
 
     fn init_logger() {
         let _ = env_logger::builder().is_test(true).try_init();
     }
 
+    fn roots_of(left: &Path, right: &Path) -> Arc<Roots> {
+        Arc::new(Roots {
+            left: left.to_path_buf(),
+            right: right.to_path_buf(),
+        })
+    }
+
+    //details of a regular file existing on one side only
+    fn file() -> EntryDetails {
+        EntryDetails {
+            kind: EntryKind::File,
+            device: None,
+            symlink: None,
+        }
+    }
+
     #[test]
     fn no_restictions() {
         init_logger();
@@ -146,26 +478,26 @@ mod tests_compare_dirs_inner {
             filter: None,
             ignore_equal: false,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<DirCmpEntry> = vec![
-            DirCmpEntry::Left(file_left_only.as_path().to_path_buf()),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
             DirCmpEntry::Both(
-                file_left_both_diff.as_path().to_path_buf(),
-                file_right_both_diff.as_path().to_path_buf(),
-                FileCompResult::Different,
+                roots.left_entry(&file_left_both_diff),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
             ),
             DirCmpEntry::Both(
-                file_left_both_equal.as_path().to_path_buf(),
-                file_right_both_equal.as_path().to_path_buf(),
+                roots.left_entry(&file_left_both_equal),
                 FileCompResult::Equal,
+                FileCompDetails::default(),
             ),
-            DirCmpEntry::Right(file_right_only.as_path().to_path_buf()),
+            DirCmpEntry::Left(roots.left_entry(&file_left_only), file()),
+            DirCmpEntry::Right(roots.right_entry(&file_right_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
     #[test]
@@ -196,21 +528,21 @@ mod tests_compare_dirs_inner {
             ignore_right_only: false,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<DirCmpEntry> = vec![
-            DirCmpEntry::Left(file_left_only.as_path().to_path_buf()),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
             DirCmpEntry::Both(
-                file_left_both_diff.as_path().to_path_buf(),
-                file_right_both_diff.as_path().to_path_buf(),
-                FileCompResult::Different,
+                roots.left_entry(&file_left_both_diff),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
             ),
-            DirCmpEntry::Right(file_right_only.as_path().to_path_buf()),
+            DirCmpEntry::Left(roots.left_entry(&file_left_only), file()),
+            DirCmpEntry::Right(roots.right_entry(&file_right_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -242,25 +574,25 @@ mod tests_compare_dirs_inner {
             ignore_right_only: false,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<DirCmpEntry> = vec![
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
             DirCmpEntry::Both(
-                file_left_both_diff.as_path().to_path_buf(),
-                file_right_both_diff.as_path().to_path_buf(),
-                FileCompResult::Different,
+                roots.left_entry(&file_left_both_diff),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
             ),
             DirCmpEntry::Both(
-                file_left_both_equal.as_path().to_path_buf(),
-                file_right_both_equal.as_path().to_path_buf(),
+                roots.left_entry(&file_left_both_equal),
                 FileCompResult::Equal,
+                FileCompDetails::default(),
             ),
-            DirCmpEntry::Right(file_right_only.as_path().to_path_buf()),
+            DirCmpEntry::Right(roots.right_entry(&file_right_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -292,26 +624,26 @@ mod tests_compare_dirs_inner {
             ignore_right_only: true,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<DirCmpEntry> = vec![
-            DirCmpEntry::Left(file_left_only.as_path().to_path_buf()),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
             DirCmpEntry::Both(
-                file_left_both_diff.as_path().to_path_buf(),
-                file_right_both_diff.as_path().to_path_buf(),
-                FileCompResult::Different,
+                roots.left_entry(&file_left_both_diff),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
             ),
             DirCmpEntry::Both(
-                file_left_both_equal.as_path().to_path_buf(),
-                file_right_both_equal.as_path().to_path_buf(),
+                roots.left_entry(&file_left_both_equal),
                 FileCompResult::Equal,
+                FileCompDetails::default(),
             ),
+            DirCmpEntry::Left(roots.left_entry(&file_left_only), file()),
         ];
-        expected.sort();
 
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -347,17 +679,17 @@ mod tests_compare_dirs_inner {
             ignore_right_only: false,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Both(
-            left_sub_dir.as_path().to_path_buf(),
-            right_sub_dir.as_path().to_path_buf(),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Both(
+            roots.left_entry(&left_sub_dir),
             FileCompResult::Equal,
+            FileCompDetails::default(),
         )];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -393,62 +725,1437 @@ mod tests_compare_dirs_inner {
             ignore_right_only: false,
             filter: None,
             recursive: true,
+            ..Default::default()
         };
 
-        let mut expected: Vec<DirCmpEntry> = vec![
-            DirCmpEntry::Left(file_left_only.as_path().to_path_buf()),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
             DirCmpEntry::Both(
-                file_left_both_diff.as_path().to_path_buf(),
-                file_right_both_diff.as_path().to_path_buf(),
-                FileCompResult::Different,
+                roots.left_entry(&file_left_both_diff),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
             ),
             DirCmpEntry::Both(
-                file_left_both_equal.as_path().to_path_buf(),
-                file_right_both_equal.as_path().to_path_buf(),
+                roots.left_entry(&file_left_both_equal),
                 FileCompResult::Equal,
+                FileCompDetails::default(),
             ),
-            DirCmpEntry::Right(file_right_only.as_path().to_path_buf()),
+            DirCmpEntry::Left(roots.left_entry(&file_left_only), file()),
+            DirCmpEntry::Right(roots.right_entry(&file_right_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
-}
 
-pub fn compare_dirs(
-    left_path: &Path,
-    right_path: &Path,
-    options: Options,
-) -> io::Result<Vec<DirCmpEntry>> {
-    debug!(
-        "starting to compare for {:?} vs {:?}",
-        left_path, right_path
-    );
+    #[test]
+    fn root_not_found() {
+        init_logger();
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_path = left_dir.path().join("missing");
+
+        let diff_options = Options {
+            ignore_equal: false,
+            ignore_left_only: false,
+            ignore_right_only: false,
+            filter: None,
+            recursive: false,
+            ..Default::default()
+        };
+
+        let result = compare_dirs(left_dir.path(), right_path.as_path(), diff_options);
+        assert!(matches!(result, Err(Error::RootNotFound(path)) if path == right_path));
+    }
+
+    #[test]
+    fn root_not_directory() {
+        init_logger();
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_file = left_dir.path().join("file.txt");
+        fs::write(left_file.as_path(), b"not a dir").unwrap();
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+
+        let diff_options = Options {
+            ignore_equal: false,
+            ignore_left_only: false,
+            ignore_right_only: false,
+            filter: None,
+            recursive: false,
+            ..Default::default()
+        };
+
+        let result = compare_dirs(left_file.as_path(), right_dir.path(), diff_options);
+        assert!(matches!(result, Err(Error::RootNotDirectory(path)) if path == left_file));
+    }
+
+    #[test]
+    fn iter_left_only_subtree() {
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub_dir = left_dir.path().join("subdir");
+        fs::create_dir(left_sub_dir.as_path()).unwrap();
+        let left_sub_sub_dir = left_sub_dir.join("subsubdir");
+        fs::create_dir(left_sub_sub_dir.as_path()).unwrap();
+        let file_left_only = left_sub_sub_dir.join("left_only.txt");
+        fs::write(file_left_only.as_path(), b"Lefty left").unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+
+        let diff_options = Options {
+            ignore_equal: false,
+            ignore_left_only: false,
+            ignore_right_only: false,
+            filter: None,
+            recursive: true,
+            ..Default::default()
+        };
+
+        let mut iter = compare_dirs_iter(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            DirCmpEntry::Left(roots.left_entry(&file_left_only), file())
+        );
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn iter_stop_early() {
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        for i in 0..10 {
+            let file_left = left_dir.path().join(format!("file{}.txt", i));
+            fs::write(file_left.as_path(), b"same same").unwrap();
+        }
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
 
-    if !left_path.exists() {
-        error!("The left path does not exists!");
-        panic!();
+        let diff_options = Options {
+            ignore_equal: false,
+            ignore_left_only: false,
+            ignore_right_only: false,
+            filter: None,
+            recursive: true,
+            ..Default::default()
+        };
+
+        let result: Vec<DirCmpEntry> =
+            compare_dirs_iter(left_dir.path(), right_dir.path(), diff_options)
+                .unwrap()
+                .take(3)
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn threads_same_as_serial() {
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub_dir = left_dir.path().join("subdir");
+        fs::create_dir(left_sub_dir.as_path()).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_sub_dir = right_dir.path().join("subdir");
+        fs::create_dir(right_sub_dir.as_path()).unwrap();
+
+        for i in 0..50 {
+            let name = format!("file{}.txt", i);
+            fs::write(left_dir.path().join(&name), b"same same").unwrap();
+            fs::write(right_dir.path().join(&name), b"same same").unwrap();
+            fs::write(left_sub_dir.join(&name), format!("left {}", i % 3)).unwrap();
+            fs::write(right_sub_dir.join(&name), format!("left {}", i % 5)).unwrap();
+        }
+
+        let serial_options = Options {
+            recursive: true,
+            threads: 1,
+            ..Default::default()
+        };
+        let parallel_options = Options {
+            recursive: true,
+            threads: 4,
+            ..Default::default()
+        };
+
+        let serial = compare_dirs(left_dir.path(), right_dir.path(), serial_options).unwrap();
+        let parallel = compare_dirs(left_dir.path(), right_dir.path(), parallel_options).unwrap();
+        assert_eq!(serial.len(), 100);
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn compare_mode_size_only() {
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let file_left_same_size = left_dir.path().join("same_size.txt");
+        fs::write(file_left_same_size.as_path(), b"same size").unwrap();
+        let file_left_other_size = left_dir.path().join("other_size.txt");
+        fs::write(file_left_other_size.as_path(), b"short").unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let file_right_same_size = right_dir.path().join("same_size.txt");
+        fs::write(file_right_same_size.as_path(), b"SAME SIZE").unwrap();
+        let file_right_other_size = right_dir.path().join("other_size.txt");
+        fs::write(file_right_other_size.as_path(), b"much longer").unwrap();
+
+        let diff_options = Options {
+            compare_mode: CompareMode::SIZE_ONLY,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Both(
+                roots.left_entry(&file_left_other_size),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
+            ),
+            DirCmpEntry::Both(
+                roots.left_entry(&file_left_same_size),
+                FileCompResult::Equal,
+                FileCompDetails::default(),
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn type_mismatch() {
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_mismatch = left_dir.path().join("mismatch");
+        fs::write(left_mismatch.as_path(), b"file on the left").unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_mismatch = right_dir.path().join("mismatch");
+        fs::create_dir(right_mismatch.as_path()).unwrap();
+        fs::write(right_mismatch.join("file.txt"), b"dir on the right").unwrap();
+
+        //mismatches are reported even if equal entries are ignored
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::TypeMismatch {
+            path: roots.left_entry(&left_mismatch),
+            left_kind: EntryKind::File,
+            right_kind: EntryKind::Dir,
+        }];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn glob_filter() {
+        use crate::GlobFilter;
+        init_logger();
+        //prepare both dirs the same way, the log files differ
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        for (dir, content) in [(&left_dir, "left"), (&right_dir, "right")] {
+            fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+            fs::write(dir.path().join("target/debug/app.log"), content).unwrap();
+            fs::create_dir(dir.path().join("logs")).unwrap();
+            fs::write(dir.path().join("logs/debug.log"), content).unwrap();
+            fs::write(dir.path().join("logs/keep.log"), content).unwrap();
+            fs::write(dir.path().join("agitator.txt"), content).unwrap();
+        }
+
+        let filter = GlobFilter::new(["target/", "*.log", "!keep.log", ".git"]).unwrap();
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            filter: Some(FilterSet {
+                glob: Some(filter),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("agitator.txt")),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
+            ),
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("logs/keep.log")),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn filter_sets() {
+        use crate::GlobFilter;
+        use regex::bytes::Regex;
+        init_logger();
+        //prepare left dir, carrying mac junk
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(left_dir.path().join("main.rs"), b"fn main() {}").unwrap();
+        fs::write(left_dir.path().join("main.rs.bak"), b"fn main() {}").unwrap();
+        fs::write(left_dir.path().join(".DS_Store"), b"junk").unwrap();
+        //a left only sub tree is filtered as well
+        fs::create_dir_all(left_dir.path().join("new/target")).unwrap();
+        fs::write(left_dir.path().join("new/target/out.rs"), b"built").unwrap();
+        fs::write(left_dir.path().join("new/lib.rs"), b"pub fn new() {}").unwrap();
+
+        //prepare right dir, carrying windows junk
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(right_dir.path().join("main.rs"), b"fn main() {}").unwrap();
+        fs::write(right_dir.path().join("Thumbs.db"), b"junk").unwrap();
+
+        let glob_filter = |pattern| FilterSet {
+            glob: Some(GlobFilter::new([pattern]).unwrap()),
+            ..Default::default()
+        };
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            filter: Some(FilterSet {
+                include: vec![Regex::new(r"\.rs$").unwrap()],
+                exclude: vec![Regex::new(r"(^|/)target$").unwrap()],
+                glob: None,
+                metadata: None,
+            }),
+            left_filter: Some(glob_filter(".DS_Store")),
+            right_filter: Some(glob_filter("Thumbs.db")),
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Left(
+            roots.left_entry(&left_dir.path().join("new/lib.rs")),
+            file(),
+        )];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    //files of a small project, the contents of the left and right tree differ as given
+    fn prepare_nested_tree() -> (tempfile::TempDir, tempfile::TempDir) {
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        for (dir, side) in [(&left_dir, "left"), (&right_dir, "right")] {
+            fs::create_dir_all(dir.path().join("src/deep/nested")).unwrap();
+            fs::create_dir_all(dir.path().join("docs")).unwrap();
+            fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+            fs::write(dir.path().join("Cargo.toml"), side).unwrap();
+            fs::write(dir.path().join("README.md"), b"same same").unwrap();
+            fs::write(dir.path().join("docs/guide.md"), b"same same").unwrap();
+            fs::write(dir.path().join("src/main.rs"), b"same same").unwrap();
+            fs::write(dir.path().join("src/deep/notes.txt"), b"same same").unwrap();
+            fs::write(dir.path().join("src/deep/nested/lib.rs"), side).unwrap();
+            fs::write(dir.path().join("target/debug/build.rs"), b"same same").unwrap();
+        }
+        fs::create_dir_all(left_dir.path().join("left_only/sub")).unwrap();
+        fs::write(left_dir.path().join("left_only/sub/extra.rs"), b"left").unwrap();
+        fs::write(left_dir.path().join("left_only/sub/extra.md"), b"left").unwrap();
+        fs::create_dir(right_dir.path().join("right_only")).unwrap();
+        fs::write(right_dir.path().join("right_only/only.md"), b"right").unwrap();
+        (left_dir, right_dir)
+    }
+
+    //compares the nested tree recursively and returns the reported relative paths
+    fn compare_nested_tree(filter: FilterSet) -> Vec<(&'static str, PathBuf)> {
+        compare_nested_tree_with(Options {
+            recursive: true,
+            filter: Some(filter),
+            ..Default::default()
+        })
+    }
+
+    fn compare_nested_tree_with(diff_options: Options) -> Vec<(&'static str, PathBuf)> {
+        let (left_dir, right_dir) = prepare_nested_tree();
+        compare_dirs(left_dir.path(), right_dir.path(), diff_options)
+            .unwrap()
+            .into_iter()
+            .map(|entry| {
+                let side = match &entry {
+                    DirCmpEntry::Both(_, FileCompResult::Equal, _) => "equal",
+                    DirCmpEntry::Both(..) => "different",
+                    DirCmpEntry::Left(_, _) => "left",
+                    DirCmpEntry::Right(_, _) => "right",
+                    DirCmpEntry::TypeMismatch { .. } => "mismatch",
+                    DirCmpEntry::Unexplored { sides, .. } => match sides {
+                        Sides::Both => "unexplored",
+                        Sides::Left => "unexplored left",
+                        Sides::Right => "unexplored right",
+                    },
+                };
+                (side, entry.path().relative().to_path_buf())
+            })
+            .collect()
+    }
+
+    fn include(patterns: &[&str]) -> FilterSet {
+        FilterSet {
+            include: patterns
+                .iter()
+                .map(|pattern| regex::bytes::Regex::new(pattern).unwrap())
+                .collect(),
+            ..Default::default()
+        }
     }
 
-    if !left_path.is_dir() {
-        error!("The left path is not a directory!");
-        panic!();
+    #[test]
+    fn include_nested_files() {
+        init_logger();
+        //the directories are walked although their names do not match
+        let expected = vec![
+            ("left", "left_only/sub/extra.rs".into()),
+            ("different", "src/deep/nested/lib.rs".into()),
+            ("equal", "src/main.rs".into()),
+            ("equal", "target/debug/build.rs".into()),
+        ];
+        assert_eq!(compare_nested_tree(include(&[r"\.rs$"])), expected);
     }
 
-    if !right_path.exists() {
-        error!("The right path does not exists!");
-        panic!();
+    #[test]
+    fn include_any_pattern() {
+        init_logger();
+        let expected = vec![
+            ("different", "Cargo.toml".into()),
+            ("equal", "README.md".into()),
+            ("equal", "docs/guide.md".into()),
+            ("left", "left_only/sub/extra.md".into()),
+            ("right", "right_only/only.md".into()),
+        ];
+        assert_eq!(
+            compare_nested_tree(include(&[r"\.md$", r"^Cargo\.toml$"])),
+            expected
+        );
     }
 
-    if !right_path.is_dir() {
-        error!("The right path is not a directory!");
-        panic!();
+    #[test]
+    fn include_anchored_in_sub_dir() {
+        init_logger();
+        //the pattern matches the path relative to the root, not the file name
+        let expected = vec![
+            ("different", "src/deep/nested/lib.rs".into()),
+            ("equal", "src/deep/notes.txt".into()),
+        ];
+        assert_eq!(compare_nested_tree(include(&[r"^src/deep/"])), expected);
     }
 
-    let left_base = left_path.to_str().unwrap();
-    let right_base = right_path.to_str().unwrap();
+    #[test]
+    fn include_with_exclude() {
+        init_logger();
+        //excluded directories are pruned even if they contain included files
+        let filter = FilterSet {
+            exclude: vec![regex::bytes::Regex::new(r"^(target|left_only)$").unwrap()],
+            ..include(&[r"\.rs$"])
+        };
+        let expected = vec![
+            ("different", "src/deep/nested/lib.rs".into()),
+            ("equal", "src/main.rs".into()),
+        ];
+        assert_eq!(compare_nested_tree(filter), expected);
+    }
 
-    compare_dirs_inner(left_path, right_path, left_base, right_base, &options)
+    #[test]
+    fn include_nothing_matches() {
+        init_logger();
+        assert_eq!(compare_nested_tree(include(&[r"\.py$"])), vec![]);
+    }
+
+    #[test]
+    fn max_depth() {
+        init_logger();
+        //directories at the limit are reported instead of being compared as equal
+        let expected = vec![
+            ("different", "Cargo.toml".into()),
+            ("unexplored left", "left_only/sub".into()),
+            ("right", "right_only/only.md".into()),
+            ("unexplored", "src/deep".into()),
+            ("unexplored", "target/debug".into()),
+        ];
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(compare_nested_tree_with(diff_options), expected);
+
+        let diff_options = Options {
+            recursive: true,
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(compare_nested_tree_with(diff_options), vec![]);
+    }
+
+    #[test]
+    fn min_depth() {
+        init_logger();
+        let expected = vec![
+            ("left", "left_only/sub/extra.md".into()),
+            ("left", "left_only/sub/extra.rs".into()),
+            ("different", "src/deep/nested/lib.rs".into()),
+        ];
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            min_depth: 3,
+            ..Default::default()
+        };
+        assert_eq!(compare_nested_tree_with(diff_options), expected);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn one_file_system() {
+        use std::os::unix::fs::symlink;
+        init_logger();
+        //followed symlinks into procfs stand in for mount points
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        for dir in [&left_dir, &right_dir] {
+            fs::write(dir.path().join("file.txt"), b"same same").unwrap();
+            symlink("/proc/self", dir.path().join("proc")).unwrap();
+        }
+        //a mount point on one side only stops the walk on both sides
+        symlink("/proc/self", left_dir.path().join("mixed")).unwrap();
+        fs::create_dir(right_dir.path().join("mixed")).unwrap();
+        fs::write(right_dir.path().join("mixed/file.txt"), b"Righty right").unwrap();
+        symlink("/proc/self", right_dir.path().join("right_only")).unwrap();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            symlinks: SymlinkMode::Follow,
+            one_file_system: true,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let followed = || Some(SymlinkKind::Followed(PathBuf::from("/proc/self")));
+        let mount_point = |path, sides, left_symlink, right_symlink| DirCmpEntry::Unexplored {
+            path,
+            sides,
+            reason: Unexplored::MountPoint,
+            details: FileCompDetails {
+                left_symlink,
+                right_symlink,
+                ..Default::default()
+            },
+        };
+        let expected: Vec<DirCmpEntry> = vec![
+            mount_point(
+                roots.left_entry(&left_dir.path().join("mixed")),
+                Sides::Both,
+                followed(),
+                None,
+            ),
+            mount_point(
+                roots.left_entry(&left_dir.path().join("proc")),
+                Sides::Both,
+                followed(),
+                followed(),
+            ),
+            mount_point(
+                roots.right_entry(&right_dir.path().join("right_only")),
+                Sides::Right,
+                None,
+                followed(),
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn case_insensitive_names() {
+        use crate::NameMatching;
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(left_dir.path().join("Empty")).unwrap();
+        fs::create_dir(left_dir.path().join("Src")).unwrap();
+        fs::write(left_dir.path().join("Src/lib.rs"), b"same same").unwrap();
+        fs::write(left_dir.path().join("Src/main.rs"), b"Lefty left").unwrap();
+        fs::write(left_dir.path().join("README.md"), b"same same").unwrap();
+        fs::write(left_dir.path().join("same.txt"), b"same same").unwrap();
+
+        //prepare right dir, copied from a case-insensitive share
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(right_dir.path().join("empty")).unwrap();
+        fs::create_dir(right_dir.path().join("src")).unwrap();
+        fs::write(right_dir.path().join("src/lib.rs"), b"same same").unwrap();
+        fs::write(right_dir.path().join("src/main.rs"), b"Righty right").unwrap();
+        fs::write(right_dir.path().join("src/new.rs"), b"Righty right").unwrap();
+        fs::write(right_dir.path().join("Readme.md"), b"same same").unwrap();
+        fs::write(right_dir.path().join("same.txt"), b"same same").unwrap();
+
+        //equal entries are reported if their own spellings differ, directories only once
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            name_matching: NameMatching {
+                case_insensitive: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let readme = roots.pair_entry(
+            &left_dir.path().join("README.md"),
+            &right_dir.path().join("Readme.md"),
+        );
+        let main = roots.pair_entry(
+            &left_dir.path().join("Src/main.rs"),
+            &right_dir.path().join("src/main.rs"),
+        );
+        let dir_entry = |left: &str, right: &str| {
+            DirCmpEntry::Both(
+                roots.pair_entry(&left_dir.path().join(left), &right_dir.path().join(right)),
+                FileCompResult::Equal,
+                FileCompDetails::default(),
+            )
+        };
+        let expected: Vec<DirCmpEntry> = vec![
+            dir_entry("Empty", "empty"),
+            DirCmpEntry::Both(readme, FileCompResult::Equal, FileCompDetails::default()),
+            dir_entry("Src", "src"),
+            DirCmpEntry::Both(
+                main,
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
+            ),
+            DirCmpEntry::Right(
+                roots.right_entry(&right_dir.path().join("src/new.rs")),
+                file(),
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn normalized_names() {
+        use crate::{NameDifference, NameMatching};
+        init_logger();
+        let nfc = "caf\u{e9}.txt";
+        let nfd = "cafe\u{301}.txt";
+        //prepare left dir, written on linux
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(left_dir.path().join(nfc), b"same same").unwrap();
+        fs::write(left_dir.path().join("Caf\u{e9}.txt"), b"Lefty left").unwrap();
+        fs::create_dir(left_dir.path().join("r\u{e9}sum\u{e9}")).unwrap();
+        fs::write(
+            left_dir.path().join("r\u{e9}sum\u{e9}/cv.txt"),
+            b"same same",
+        )
+        .unwrap();
+
+        //prepare right dir, copied from macOS
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(right_dir.path().join(nfd), b"same same").unwrap();
+        fs::create_dir(right_dir.path().join("re\u{301}sume\u{301}")).unwrap();
+        fs::write(
+            right_dir.path().join("re\u{301}sume\u{301}/cv.txt"),
+            b"same same",
+        )
+        .unwrap();
+
+        //the contents of a directory spelled differently are not flagged
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            name_matching: NameMatching {
+                unicode_normalization: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let cafe = roots.pair_entry(&left_dir.path().join(nfc), &right_dir.path().join(nfd));
+        assert_eq!(cafe.name_difference(), Some(NameDifference::Normalization));
+        let resume = roots.pair_entry(
+            &left_dir.path().join("r\u{e9}sum\u{e9}"),
+            &right_dir.path().join("re\u{301}sume\u{301}"),
+        );
+        assert_eq!(
+            resume.name_difference(),
+            Some(NameDifference::Normalization)
+        );
+        //names differing in case are not matched
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Left(
+                roots.left_entry(&left_dir.path().join("Caf\u{e9}.txt")),
+                file(),
+            ),
+            DirCmpEntry::Both(cafe, FileCompResult::Equal, FileCompDetails::default()),
+            DirCmpEntry::Both(resume, FileCompResult::Equal, FileCompDetails::default()),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn metadata_filter() {
+        use crate::MetadataFilter;
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(left_dir.path().join("sub")).unwrap();
+        fs::write(left_dir.path().join("sub/small.txt"), b"small").unwrap();
+        fs::write(left_dir.path().join("sub/large.bin"), [0u8; 100]).unwrap();
+        fs::write(left_dir.path().join("grown.bin"), b"small").unwrap();
+        fs::write(left_dir.path().join("large_left.bin"), [0u8; 100]).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(right_dir.path().join("sub")).unwrap();
+        fs::write(right_dir.path().join("sub/small.txt"), b"SMALL").unwrap();
+        fs::write(right_dir.path().join("sub/large.bin"), [1u8; 100]).unwrap();
+        fs::write(right_dir.path().join("grown.bin"), [0u8; 100]).unwrap();
+
+        //the directory is walked although its size is not checked
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            filter: Some(FilterSet {
+                metadata: Some(MetadataFilter {
+                    max_size: Some(10),
+                    kinds: vec![EntryKind::File],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        //pairs are compared if either side satisfies the filter
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("grown.bin")),
+                FileCompResult::Different(Check::Size),
+                FileCompDetails::default(),
+            ),
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("sub/small.txt")),
+                FileCompResult::Different(Check::Content),
+                FileCompDetails::default(),
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn metadata_filter_with_patterns() {
+        use crate::MetadataFilter;
+        use std::os::unix::fs::PermissionsExt;
+        use std::process::Command;
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(left_dir.path().join("run.sh"), b"#!/bin/sh").unwrap();
+        fs::set_permissions(
+            left_dir.path().join("run.sh"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::write(left_dir.path().join("data.sh"), b"#!/bin/sh").unwrap();
+        fs::write(left_dir.path().join("tool"), b"binary").unwrap();
+        fs::set_permissions(
+            left_dir.path().join("tool"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        assert!(Command::new("mkfifo")
+            .arg(left_dir.path().join("pipe.sh"))
+            .status()
+            .unwrap()
+            .success());
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+
+        //only executable shell scripts
+        let diff_options = Options {
+            filter: Some(FilterSet {
+                include: vec![regex::bytes::Regex::new(r"\.sh$").unwrap()],
+                metadata: Some(MetadataFilter {
+                    executable: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Left(
+            roots.left_entry(&left_dir.path().join("run.sh")),
+            file(),
+        )];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn ignore_files() {
+        use crate::IgnoreRules;
+        init_logger();
+        //prepare both dirs the same way, the ignored files differ
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        for (dir, content) in [(&left_dir, "left"), (&right_dir, "right")] {
+            fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+            fs::create_dir_all(dir.path().join("target/debug")).unwrap();
+            fs::write(dir.path().join("target/debug/app"), content).unwrap();
+            fs::create_dir(dir.path().join("sub")).unwrap();
+            fs::write(dir.path().join("sub/.ignore"), "*.tmp\n!keep.tmp\n").unwrap();
+            fs::write(dir.path().join("sub/scratch.tmp"), content).unwrap();
+            fs::write(dir.path().join("sub/keep.tmp"), content).unwrap();
+            fs::write(dir.path().join("notes.txt"), content).unwrap();
+        }
+        //only the left side ignores the notes
+        fs::write(left_dir.path().join(".dircmpignore"), "notes.txt\n").unwrap();
+        //the right only sub tree is ignored by the files of the right side
+        fs::create_dir(right_dir.path().join("new")).unwrap();
+        fs::write(right_dir.path().join("new/.gitignore"), "*.tmp\n").unwrap();
+        fs::write(right_dir.path().join("new/scratch.tmp"), "right").unwrap();
+
+        let compare = |rules| {
+            let diff_options = Options {
+                ignore_equal: true,
+                recursive: true,
+                ignore_files: Some(rules),
+                ..Default::default()
+            };
+            compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let keep = DirCmpEntry::Both(
+            roots.left_entry(&left_dir.path().join("sub/keep.tmp")),
+            FileCompResult::Different(Check::Size),
+            FileCompDetails::default(),
+        );
+        let notes = DirCmpEntry::Both(
+            roots.left_entry(&left_dir.path().join("notes.txt")),
+            FileCompResult::Different(Check::Size),
+            FileCompDetails::default(),
+        );
+        let left_only = DirCmpEntry::Left(
+            roots.left_entry(&left_dir.path().join(".dircmpignore")),
+            file(),
+        );
+        let right_only = DirCmpEntry::Right(
+            roots.right_entry(&right_dir.path().join("new/.gitignore")),
+            file(),
+        );
+
+        assert_eq!(
+            compare(IgnoreRules::Either),
+            vec![left_only.clone(), right_only.clone(), keep.clone()]
+        );
+        assert_eq!(
+            compare(IgnoreRules::Right),
+            vec![left_only, right_only, notes, keep]
+        );
+    }
+
+    #[test]
+    fn ignore_files_respelled_dir() {
+        use crate::{IgnoreRules, NameMatching};
+        init_logger();
+        //the directory only matches case-insensitively, both sides ignore the logs
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(left_dir.path().join("Sub")).unwrap();
+        fs::write(left_dir.path().join("Sub/.gitignore"), "*.log\n").unwrap();
+        fs::write(left_dir.path().join("Sub/y.log"), "left").unwrap();
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(right_dir.path().join("sub")).unwrap();
+        fs::write(right_dir.path().join("sub/.gitignore"), "*.log\n").unwrap();
+        fs::write(right_dir.path().join("sub/x.log"), "right").unwrap();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            ignore_files: Some(IgnoreRules::Both),
+            name_matching: NameMatching {
+                case_insensitive: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Both(
+            roots.pair_entry(&left_dir.path().join("Sub"), &right_dir.path().join("sub")),
+            FileCompResult::Equal,
+            FileCompDetails::default(),
+        )];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn metadata_checks() {
+        use crate::{MetadataChecks, MetadataDiff};
+        use std::os::unix::fs::PermissionsExt;
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_file = left_dir.path().join("file.txt");
+        fs::write(left_file.as_path(), b"same same").unwrap();
+        fs::set_permissions(&left_file, fs::Permissions::from_mode(0o644)).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_file = right_dir.path().join("file.txt");
+        fs::write(right_file.as_path(), b"same same").unwrap();
+        fs::set_permissions(&right_file, fs::Permissions::from_mode(0o777)).unwrap();
+
+        //files with equal contents are reported if their metadata differs
+        let diff_options = Options {
+            ignore_equal: true,
+            metadata_checks: MetadataChecks {
+                mode: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Both(
+            roots.left_entry(&left_file),
+            FileCompResult::Equal,
+            FileCompDetails {
+                metadata: vec![MetadataDiff::Mode {
+                    left: 0o644,
+                    right: 0o777,
+                }],
+                ..Default::default()
+            },
+        )];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn metadata_checks_dirs_and_specials() {
+        use crate::{MetadataChecks, MetadataDiff, SpecialFiles};
+        use std::os::unix::fs::PermissionsExt;
+        use std::process::Command;
+        init_logger();
+        let prepare = |dir_mode, fifo_mode| {
+            let dir = tempfile::Builder::new().tempdir().unwrap();
+            let etc = dir.path().join("etc");
+            fs::create_dir(&etc).unwrap();
+            fs::write(etc.join("file.txt"), b"same same").unwrap();
+            let fifo = etc.join("fifo");
+            assert!(Command::new("mkfifo")
+                .arg(&fifo)
+                .status()
+                .unwrap()
+                .success());
+            fs::set_permissions(&fifo, fs::Permissions::from_mode(fifo_mode)).unwrap();
+            fs::set_permissions(&etc, fs::Permissions::from_mode(dir_mode)).unwrap();
+            dir
+        };
+        let left_dir = prepare(0o700, 0o600);
+        let right_dir = prepare(0o777, 0o644);
+
+        //the directory is reported before its contents, the equal file is not reported
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            metadata_checks: MetadataChecks {
+                mode: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("etc")),
+                FileCompResult::Equal,
+                FileCompDetails {
+                    metadata: vec![MetadataDiff::Mode {
+                        left: 0o700,
+                        right: 0o777,
+                    }],
+                    ..Default::default()
+                },
+            ),
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("etc/fifo")),
+                FileCompResult::Equal,
+                FileCompDetails {
+                    metadata: vec![MetadataDiff::Mode {
+                        left: 0o600,
+                        right: 0o644,
+                    }],
+                    special: Some(SpecialFiles {
+                        kind: EntryKind::Fifo,
+                        devices: None,
+                    }),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn xattrs_dirs() {
+        use crate::{XattrChecks, XattrDiff};
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub = left_dir.path().join("sub");
+        fs::create_dir(&left_sub).unwrap();
+        //not every file system used for temporary files supports user attributes
+        if xattr::set(&left_sub, "user.comment", b"left").is_err() {
+            return;
+        }
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(right_dir.path().join("sub")).unwrap();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            xattrs: Some(XattrChecks::default()),
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Both(
+            roots.left_entry(&left_sub),
+            FileCompResult::Equal,
+            FileCompDetails {
+                xattrs: vec![XattrDiff::Removed {
+                    name: "user.comment".into(),
+                    value: b"left".to_vec(),
+                }],
+                ..Default::default()
+            },
+        )];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links() {
+        use crate::HardLinkDiff;
+        init_logger();
+        //prepare left dir, all files are linked
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(left_dir.path().join("a.txt"), b"same same").unwrap();
+        fs::hard_link(left_dir.path().join("a.txt"), left_dir.path().join("b.txt")).unwrap();
+        fs::hard_link(left_dir.path().join("a.txt"), left_dir.path().join("c.txt")).unwrap();
+
+        //prepare right dir, c is a separate copy
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(right_dir.path().join("a.txt"), b"same same").unwrap();
+        fs::hard_link(
+            right_dir.path().join("a.txt"),
+            right_dir.path().join("b.txt"),
+        )
+        .unwrap();
+        fs::write(right_dir.path().join("c.txt"), b"same same").unwrap();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            hard_links: true,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Both(
+            roots.left_entry(&left_dir.path().join("c.txt")),
+            FileCompResult::Equal,
+            FileCompDetails {
+                hard_links: Some(HardLinkDiff {
+                    left: Some("a.txt".into()),
+                    right: None,
+                }),
+                ..Default::default()
+            },
+        )];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+
+        //the linked files are compared once, but still reported for each name
+        let diff_options = Options {
+            hard_links: true,
+            threads: 2,
+            ..Default::default()
+        };
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(result
+            .iter()
+            .all(|entry| matches!(entry, DirCmpEntry::Both(_, FileCompResult::Equal, _))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files() {
+        use crate::SpecialFiles;
+        use std::os::unix::net::UnixListener;
+        use std::process::Command;
+        init_logger();
+        let mkfifo = |path: &Path| {
+            assert!(Command::new("mkfifo").arg(path).status().unwrap().success());
+        };
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        mkfifo(&left_dir.path().join("fifo"));
+        mkfifo(&left_dir.path().join("mismatch"));
+        let _left_socket = UnixListener::bind(left_dir.path().join("socket")).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        mkfifo(&right_dir.path().join("fifo"));
+        fs::write(right_dir.path().join("mismatch"), b"file on the right").unwrap();
+
+        //fifos would block forever if they were opened
+        let diff_options = Options {
+            compare_mode: CompareMode::CONTENT,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("fifo")),
+                FileCompResult::Equal,
+                FileCompDetails {
+                    special: Some(SpecialFiles {
+                        kind: EntryKind::Fifo,
+                        devices: None,
+                    }),
+                    ..Default::default()
+                },
+            ),
+            DirCmpEntry::TypeMismatch {
+                path: roots.left_entry(&left_dir.path().join("mismatch")),
+                left_kind: EntryKind::Fifo,
+                right_kind: EntryKind::File,
+            },
+            DirCmpEntry::Left(
+                roots.left_entry(&left_dir.path().join("socket")),
+                EntryDetails {
+                    kind: EntryKind::Socket,
+                    device: None,
+                    symlink: None,
+                },
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn non_utf8_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        init_logger();
+        //latin-1 encoded names as found on legacy shares
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        let left_only_name = OsStr::from_bytes(b"na\xefve.txt");
+
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(left_dir.path().join(name), b"same same").unwrap();
+        fs::write(left_dir.path().join(left_only_name), b"Lefty left").unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(right_dir.path().join(name), b"same same").unwrap();
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join(name)),
+                FileCompResult::Equal,
+                FileCompDetails::default(),
+            ),
+            DirCmpEntry::Left(
+                roots.left_entry(&left_dir.path().join(left_only_name)),
+                file(),
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), Options::default()).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    fn prepare_symlinks() -> (tempfile::TempDir, tempfile::TempDir) {
+        use std::os::unix::fs::symlink;
+
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(left_dir.path().join("file.txt"), b"same same").unwrap();
+        symlink("file.txt", left_dir.path().join("same_target")).unwrap();
+        symlink("file.txt", left_dir.path().join("other_target")).unwrap();
+        symlink("file.txt", left_dir.path().join("replaced")).unwrap();
+        symlink("missing", left_dir.path().join("dangling")).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(right_dir.path().join("file.txt"), b"same same").unwrap();
+        fs::write(right_dir.path().join("other.txt"), b"same same").unwrap();
+        symlink("file.txt", right_dir.path().join("same_target")).unwrap();
+        symlink("other.txt", right_dir.path().join("other_target")).unwrap();
+        fs::write(right_dir.path().join("replaced"), b"same same").unwrap();
+        fs::write(right_dir.path().join("dangling"), b"same same").unwrap();
+
+        (left_dir, right_dir)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_ignored() {
+        init_logger();
+        let (left_dir, right_dir) = prepare_symlinks();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![DirCmpEntry::Right(
+            roots.right_entry(&right_dir.path().join("other.txt")),
+            file(),
+        )];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_target() {
+        init_logger();
+        let (left_dir, right_dir) = prepare_symlinks();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            symlinks: SymlinkMode::Target,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::TypeMismatch {
+                path: roots.left_entry(&left_dir.path().join("dangling")),
+                left_kind: EntryKind::Symlink,
+                right_kind: EntryKind::File,
+            },
+            DirCmpEntry::Right(
+                roots.right_entry(&right_dir.path().join("other.txt")),
+                file(),
+            ),
+            DirCmpEntry::Both(
+                roots.left_entry(&left_dir.path().join("other_target")),
+                FileCompResult::Different(Check::SymlinkTarget),
+                FileCompDetails {
+                    left_symlink: Some(SymlinkKind::Link("file.txt".into())),
+                    right_symlink: Some(SymlinkKind::Link("other.txt".into())),
+                    ..Default::default()
+                },
+            ),
+            DirCmpEntry::TypeMismatch {
+                path: roots.left_entry(&left_dir.path().join("replaced")),
+                left_kind: EntryKind::Symlink,
+                right_kind: EntryKind::File,
+            },
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_follow() {
+        use std::os::unix::fs::symlink;
+        init_logger();
+        let (left_dir, right_dir) = prepare_symlinks();
+        symlink("missing", left_dir.path().join("left_only_dangling")).unwrap();
+        symlink("file.txt", left_dir.path().join("left_only_link")).unwrap();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            symlinks: SymlinkMode::Follow,
+            ..Default::default()
+        };
+
+        //the followed files have equal contents, only the dangling symlink is reported,
+        //one sided entries keep their symlink kind
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::TypeMismatch {
+                path: roots.left_entry(&left_dir.path().join("dangling")),
+                left_kind: EntryKind::Symlink,
+                right_kind: EntryKind::File,
+            },
+            DirCmpEntry::Left(
+                roots.left_entry(&left_dir.path().join("left_only_dangling")),
+                EntryDetails {
+                    kind: EntryKind::Symlink,
+                    device: None,
+                    symlink: Some(SymlinkKind::Dangling("missing".into())),
+                },
+            ),
+            DirCmpEntry::Left(
+                roots.left_entry(&left_dir.path().join("left_only_link")),
+                EntryDetails {
+                    kind: EntryKind::File,
+                    device: None,
+                    symlink: Some(SymlinkKind::Followed("file.txt".into())),
+                },
+            ),
+            DirCmpEntry::Right(
+                roots.right_entry(&right_dir.path().join("other.txt")),
+                file(),
+            ),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+
+        //followed symlinks are reported in the details
+        let diff_options = Options {
+            symlinks: SymlinkMode::Follow,
+            ..Default::default()
+        };
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert!(result.contains(&DirCmpEntry::Both(
+            roots.left_entry(&left_dir.path().join("replaced")),
+            FileCompResult::Equal,
+            FileCompDetails {
+                left_symlink: Some(SymlinkKind::Followed("file.txt".into())),
+                ..Default::default()
+            },
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_follow_cycle() {
+        use std::os::unix::fs::symlink;
+        init_logger();
+        //prepare left dir, the symlink points back to its parent
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub_dir = left_dir.path().join("sub_dir");
+        fs::create_dir(&left_sub_dir).unwrap();
+        fs::write(left_sub_dir.join("file.txt"), b"same same").unwrap();
+        symlink("..", left_sub_dir.join("loop")).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_sub_dir = right_dir.path().join("sub_dir");
+        fs::create_dir(&right_sub_dir).unwrap();
+        fs::write(right_sub_dir.join("file.txt"), b"same same").unwrap();
+        symlink("..", right_sub_dir.join("loop")).unwrap();
+
+        let diff_options = Options {
+            recursive: true,
+            symlinks: SymlinkMode::Follow,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Both(
+                roots.left_entry(&left_sub_dir.join("file.txt")),
+                FileCompResult::Equal,
+                FileCompDetails::default(),
+            ),
+            DirCmpEntry::Unexplored {
+                path: roots.left_entry(&left_sub_dir.join("loop")),
+                sides: Sides::Both,
+                reason: Unexplored::SymlinkCycle,
+                details: FileCompDetails {
+                    left_symlink: Some(SymlinkKind::Followed("..".into())),
+                    right_symlink: Some(SymlinkKind::Followed("..".into())),
+                    ..Default::default()
+                },
+            },
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_follow_cycle_one_side() {
+        use std::os::unix::fs::symlink;
+        init_logger();
+        //prepare left dir, the symlink points back to its parent
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub_dir = left_dir.path().join("sub");
+        fs::create_dir(&left_sub_dir).unwrap();
+        symlink("..", left_sub_dir.join("loop")).unwrap();
+
+        //prepare right dir, holding a real directory with the same name
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_loop_dir = right_dir.path().join("sub/loop");
+        fs::create_dir_all(&right_loop_dir).unwrap();
+        fs::write(right_loop_dir.join("secret"), b"Righty right").unwrap();
+
+        let diff_options = Options {
+            ignore_equal: true,
+            recursive: true,
+            symlinks: SymlinkMode::Follow,
+            ..Default::default()
+        };
+
+        //the right side is walked as existing on that side only
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<DirCmpEntry> = vec![
+            DirCmpEntry::Unexplored {
+                path: roots.left_entry(&left_sub_dir.join("loop")),
+                sides: Sides::Left,
+                reason: Unexplored::SymlinkCycle,
+                details: FileCompDetails {
+                    left_symlink: Some(SymlinkKind::Followed("..".into())),
+                    ..Default::default()
+                },
+            },
+            DirCmpEntry::Right(roots.right_entry(&right_loop_dir.join("secret")), file()),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
 }
@@ -0,0 +1,257 @@
+use regex::bytes::Regex;
+use std::path::Path;
+
+use crate::Error;
+
+//a single line of a gitignore style pattern list
+#[derive(Debug, Clone)]
+struct GlobPattern {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// Gitignore style patterns matched against the path of an entry relative to the compared root.
+///
+/// - patterns without a `/`, apart from a trailing one, match the name at any depth,
+///   patterns with a leading or inner `/` are anchored at the root
+/// - `*` and `?` do not match `/`, `**` matches any number of directories
+/// - a trailing `/` only matches directories
+/// - a leading `!` re-includes entries excluded by an earlier pattern, the last matching
+///   pattern wins
+///
+/// Excluded directories are not descended into, so entries below them can not be re-included.
+#[derive(Debug, Clone)]
+pub struct GlobFilter {
+    patterns: Vec<GlobPattern>,
+}
+
+impl GlobFilter {
+    /// Parses one pattern per item, empty items and `#` comments are skipped.
+    pub fn new<I, S>(patterns: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = patterns
+            .into_iter()
+            .filter_map(|line| parse_pattern(line.as_ref()).transpose())
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(GlobFilter { patterns })
+    }
+
+    /// true if the relative path is excluded by the patterns
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.matched(path, is_dir) == Some(true)
+    }
+
+    //returns the decision of the last matching pattern, `Some(false)` for a negated one
+    pub(crate) fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let path = path.as_os_str().as_encoded_bytes();
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| (is_dir || !pattern.dir_only) && pattern.regex.is_match(path))
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+fn parse_pattern(line: &str) -> Result<Option<GlobPattern>, Error> {
+    let mut pattern = trim_trailing_spaces(line);
+    if pattern.is_empty() || pattern.starts_with('#') {
+        return Ok(None);
+    }
+    let negated = pattern.starts_with('!');
+    if negated {
+        pattern = &pattern[1..];
+    }
+    let dir_only = pattern.ends_with('/');
+    if dir_only {
+        pattern = &pattern[..pattern.len() - 1];
+    }
+    //a slash anywhere but at the end anchors the pattern at the root
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+
+    let mut regex = String::from("^");
+    if !anchored {
+        regex.push_str("(?:(?s-u:.)*/)?");
+    }
+    regex.push_str(&translate(pattern));
+    regex.push('$');
+    let regex = Regex::new(&regex).map_err(|source| Error::InvalidPattern {
+        pattern: line.to_string(),
+        source,
+    })?;
+    Ok(Some(GlobPattern {
+        regex,
+        negated,
+        dir_only,
+    }))
+}
+
+//trailing spaces are ignored unless they are escaped with a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let trimmed = line.trim_end_matches(' ');
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        &line[..trimmed.len() + 1]
+    } else {
+        trimmed
+    }
+}
+
+//translates a glob without leading slash into a regular expression
+fn translate(pattern: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = pattern.chars().peekable();
+    let mut at_segment_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') && at_segment_start => {
+                chars.next();
+                match chars.peek() {
+                    //leading or inner `**/` matches any number of directories
+                    Some('/') => {
+                        chars.next();
+                        regex.push_str("(?:(?s-u:.)*/)?");
+                        continue;
+                    }
+                    //trailing `/**` matches everything inside
+                    None => regex.push_str("(?s-u:.)*"),
+                    //any other `**` is a plain `*`
+                    Some(_) => regex.push_str("(?-u:[^/])*"),
+                }
+            }
+            '*' => regex.push_str("(?-u:[^/])*"),
+            '?' => regex.push_str("(?-u:[^/])"),
+            '[' => match char_class(&mut chars) {
+                Some(class) => regex.push_str(&class),
+                None => regex.push_str(r"\["),
+            },
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        at_segment_start = c == '/';
+    }
+    regex
+}
+
+//translates the rest of a `[...]` class, `None` if it is not closed
+fn char_class(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let mut lookahead = chars.clone();
+    let mut class = String::from("[");
+    let negated = matches!(lookahead.peek(), Some('!') | Some('^'));
+    if negated {
+        lookahead.next();
+        class.push('^');
+    }
+    let mut first = true;
+    loop {
+        match lookahead.next()? {
+            //a `]` right after the opening bracket is part of the class
+            ']' if !first => break,
+            '\\' => class.push_str(&regex::escape(&lookahead.next()?.to_string())),
+            //a class never matches the separator
+            '/' => {}
+            c @ ('[' | ']' | '&' | '~' | '^') => {
+                class.push('\\');
+                class.push(c);
+            }
+            c => class.push(c),
+        }
+        first = false;
+    }
+    if negated {
+        class.push('/');
+    }
+    class.push(']');
+    *chars = lookahead;
+    Some(class)
+}
+
+#[cfg(test)]
+mod tests_glob_filter {
+    use super::*;
+
+    fn excluded(patterns: &[&str], path: &str, is_dir: bool) -> bool {
+        GlobFilter::new(patterns)
+            .unwrap()
+            .is_excluded(Path::new(path), is_dir)
+    }
+
+    #[test]
+    fn unanchored() {
+        assert!(excluded(&[".git"], ".git", true));
+        assert!(excluded(&[".git"], "sub/.git", true));
+        assert!(!excluded(&[".git"], "agitator", false));
+        assert!(!excluded(&[".git"], ".github", true));
+        assert!(excluded(&["*.o"], "src/deep/main.o", false));
+    }
+
+    #[test]
+    fn anchored() {
+        assert!(excluded(&["/build"], "build", true));
+        assert!(!excluded(&["/build"], "sub/build", true));
+        assert!(excluded(&["doc/*.txt"], "doc/notes.txt", false));
+        assert!(!excluded(&["doc/*.txt"], "doc/sub/notes.txt", false));
+        assert!(!excluded(&["doc/*.txt"], "sub/doc/notes.txt", false));
+    }
+
+    #[test]
+    fn double_star() {
+        assert!(excluded(&["**/target"], "target", true));
+        assert!(excluded(&["**/target"], "a/b/target", true));
+        assert!(excluded(&["logs/**"], "logs/a/b.log", false));
+        assert!(!excluded(&["logs/**"], "logs", true));
+        assert!(excluded(&["a/**/b"], "a/b", false));
+        assert!(excluded(&["a/**/b"], "a/x/y/b", false));
+        assert!(!excluded(&["a/**/b"], "a/xb", false));
+    }
+
+    #[test]
+    fn dir_only() {
+        assert!(excluded(&["cache/"], "sub/cache", true));
+        assert!(!excluded(&["cache/"], "sub/cache", false));
+    }
+
+    #[test]
+    fn negation_last_match_wins() {
+        let patterns = ["*.log", "!keep.log"];
+        assert!(excluded(&patterns, "debug.log", false));
+        assert!(!excluded(&patterns, "sub/keep.log", false));
+        //a later pattern excludes the file again
+        assert!(excluded(
+            &["*.log", "!keep.log", "sub/*.log"],
+            "sub/keep.log",
+            false
+        ));
+    }
+
+    #[test]
+    fn syntax() {
+        assert!(excluded(&["# comment", "", "file?.[ch]"], "file1.c", false));
+        assert!(!excluded(&["file[!0-9].c"], "file1.c", false));
+        assert!(excluded(&[r"\#hash", r"\!bang"], "#hash", false));
+        assert!(excluded(&[r"\!bang"], "!bang", false));
+        assert!(excluded(&["trailing  "], "trailing", false));
+        assert!(excluded(&["[unclosed"], "[unclosed", false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"share/caf\xe9.txt"));
+        let filter = GlobFilter::new(["share/*.txt"]).unwrap();
+        assert!(filter.is_excluded(path, false));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compare::read_error;
+use crate::Error;
+
+/// Hard link structure of two files which differs between both sides.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HardLinkDiff {
+    /// path, relative to the left root, of the first compared file sharing the inode of the
+    /// left file, `None` if it is not linked to an earlier file
+    pub left: Option<PathBuf>,
+    /// path, relative to the right root, of the first compared file sharing the inode of
+    /// the right file, `None` if it is not linked to an earlier file
+    pub right: Option<PathBuf>,
+}
+
+//identity of a file with more than one hard link
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Inode {
+    device: u64,
+    inode: u64,
+}
+
+impl Inode {
+    #[cfg(unix)]
+    fn of_linked(path: &Path) -> Result<Option<Self>, Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = fs::metadata(path).map_err(read_error(path))?;
+        if metadata.nlink() < 2 {
+            return Ok(None);
+        }
+        Ok(Some(Inode {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        }))
+    }
+
+    //without inode numbers hard links can not be detected
+    #[cfg(not(unix))]
+    fn of_linked(_path: &Path) -> Result<Option<Self>, Error> {
+        Ok(None)
+    }
+}
+
+//how a file pair takes part in the hard link groups of both sides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum HardLinkRole {
+    /// not linked, or linked differently on both sides
+    Single,
+    /// first pair of a group linked the same way on both sides, keyed by its relative path
+    Leader(PathBuf),
+    /// linked the same way as the given leader on both sides, so it compares the same
+    Follower(PathBuf),
+    /// both files are the same inode
+    SameInode,
+}
+
+//group of linked files on one side
+#[derive(Debug, Clone)]
+struct Group {
+    //left relative path of the pair which started the group, the same on both sides even if
+    //the names only matched by `Options::name_matching`
+    pair: PathBuf,
+    //relative path of the file which started the group, spelled as on its side
+    path: PathBuf,
+}
+
+/// Groups the compared files by inode, separately for both sides.
+///
+/// Each group is identified by the pair of the first compared file of the group.
+/// Only file pairs are tracked, files which exist on one side only do not start a group.
+pub(crate) struct HardLinkTracker {
+    left_base: PathBuf,
+    right_base: PathBuf,
+    left_groups: HashMap<Inode, Group>,
+    right_groups: HashMap<Inode, Group>,
+}
+
+impl HardLinkTracker {
+    pub(crate) fn new(left_base: &Path, right_base: &Path) -> Self {
+        HardLinkTracker {
+            left_base: left_base.to_path_buf(),
+            right_base: right_base.to_path_buf(),
+            left_groups: HashMap::new(),
+            right_groups: HashMap::new(),
+        }
+    }
+
+    //returns the role of the pair and the differing structure, if any
+    pub(crate) fn visit(
+        &mut self,
+        left_path: &Path,
+        right_path: &Path,
+    ) -> Result<(HardLinkRole, Option<HardLinkDiff>), Error> {
+        let left_inode = Inode::of_linked(left_path)?;
+        let right_inode = Inode::of_linked(right_path)?;
+        let left_relative = left_path.strip_prefix(&self.left_base).unwrap();
+        let right_relative = right_path.strip_prefix(&self.right_base).unwrap();
+
+        let left_group = left_inode
+            .map(|inode| group_of(&mut self.left_groups, inode, left_relative, left_relative));
+        let right_group = right_inode
+            .map(|inode| group_of(&mut self.right_groups, inode, left_relative, right_relative));
+
+        if left_inode.is_some() && left_inode == right_inode {
+            return Ok((HardLinkRole::SameInode, None));
+        }
+
+        //a file starting a group is not linked to an earlier file
+        let left_linked = left_group
+            .clone()
+            .filter(|group| group.pair != left_relative);
+        let right_linked = right_group
+            .clone()
+            .filter(|group| group.pair != left_relative);
+        let pair_of = |group: &Option<Group>| group.as_ref().map(|group| group.pair.clone());
+        if pair_of(&left_linked) != pair_of(&right_linked) {
+            let diff = HardLinkDiff {
+                left: left_linked.map(|group| group.path),
+                right: right_linked.map(|group| group.path),
+            };
+            return Ok((HardLinkRole::Single, Some(diff)));
+        }
+
+        let role = match (left_linked, left_group, right_group.is_some()) {
+            (Some(group), _, _) => HardLinkRole::Follower(group.pair),
+            (None, Some(group), true) => HardLinkRole::Leader(group.pair),
+            _ => HardLinkRole::Single,
+        };
+        Ok((role, None))
+    }
+}
+
+//returns the group of the inode, starting a new group at the pair if there is none yet
+fn group_of(groups: &mut HashMap<Inode, Group>, inode: Inode, pair: &Path, path: &Path) -> Group {
+    groups
+        .entry(inode)
+        .or_insert_with(|| Group {
+            pair: pair.to_path_buf(),
+            path: path.to_path_buf(),
+        })
+        .clone()
+}
+
+#[cfg(all(test, unix))]
+mod tests_hard_link_tracker {
+    use super::*;
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("hard_link_tracker_")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn roles() {
+        let left_dir = create_temp_dir();
+        let right_dir = create_temp_dir();
+        for dir in [&left_dir, &right_dir] {
+            fs::write(dir.path().join("a"), b"linked").unwrap();
+            fs::hard_link(dir.path().join("a"), dir.path().join("b")).unwrap();
+            fs::write(dir.path().join("c"), b"single").unwrap();
+        }
+        //linked on the left only
+        fs::hard_link(left_dir.path().join("a"), left_dir.path().join("d")).unwrap();
+        fs::write(right_dir.path().join("d"), b"linked").unwrap();
+
+        let mut tracker = HardLinkTracker::new(left_dir.path(), right_dir.path());
+        let mut visit = |name: &str| {
+            tracker
+                .visit(&left_dir.path().join(name), &right_dir.path().join(name))
+                .unwrap()
+        };
+
+        assert_eq!(visit("a"), (HardLinkRole::Leader("a".into()), None));
+        assert_eq!(visit("b"), (HardLinkRole::Follower("a".into()), None));
+        assert_eq!(visit("c"), (HardLinkRole::Single, None));
+        assert_eq!(
+            visit("d"),
+            (
+                HardLinkRole::Single,
+                Some(HardLinkDiff {
+                    left: Some("a".into()),
+                    right: None,
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn respelled_names() {
+        let left_dir = create_temp_dir();
+        let right_dir = create_temp_dir();
+        fs::write(left_dir.path().join("A"), b"linked").unwrap();
+        fs::hard_link(left_dir.path().join("A"), left_dir.path().join("B")).unwrap();
+        fs::hard_link(left_dir.path().join("A"), left_dir.path().join("C")).unwrap();
+        fs::write(right_dir.path().join("a"), b"linked").unwrap();
+        fs::hard_link(right_dir.path().join("a"), right_dir.path().join("b")).unwrap();
+        fs::write(right_dir.path().join("c"), b"linked").unwrap();
+
+        //pairs matched case-insensitively share their groups
+        let mut tracker = HardLinkTracker::new(left_dir.path(), right_dir.path());
+        let mut visit = |left: &str, right: &str| {
+            tracker
+                .visit(&left_dir.path().join(left), &right_dir.path().join(right))
+                .unwrap()
+        };
+        assert_eq!(visit("A", "a"), (HardLinkRole::Leader("A".into()), None));
+        assert_eq!(visit("B", "b"), (HardLinkRole::Follower("A".into()), None));
+        assert_eq!(
+            visit("C", "c"),
+            (
+                HardLinkRole::Single,
+                Some(HardLinkDiff {
+                    left: Some("A".into()),
+                    right: None,
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn same_inode() {
+        let left_dir = create_temp_dir();
+        let right_dir = create_temp_dir();
+        fs::write(left_dir.path().join("a"), b"linked").unwrap();
+        fs::hard_link(left_dir.path().join("a"), right_dir.path().join("a")).unwrap();
+
+        let mut tracker = HardLinkTracker::new(left_dir.path(), right_dir.path());
+        let role = tracker
+            .visit(&left_dir.path().join("a"), &right_dir.path().join("a"))
+            .unwrap();
+        assert_eq!(role, (HardLinkRole::SameInode, None));
+    }
+}
@@ -0,0 +1,192 @@
+use sha2::{Digest as _, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::compare::{read_block, read_error, COMPARE_BLOCK_SIZE};
+use crate::Error;
+
+/// Algorithm used for `CompareMode::hash`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashAlgorithm {
+    /// cryptographic SHA-256
+    #[default]
+    Sha256,
+    /// cryptographic BLAKE3, faster than SHA-256 on most machines
+    Blake3,
+    /// fast non-cryptographic 128 bit XXH3, not safe against deliberate collisions
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "xxh3" => Some(HashAlgorithm::Xxh3),
+            _ => None,
+        }
+    }
+}
+
+/// Digest of the contents of a file.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Digest {
+    algorithm: HashAlgorithm,
+    bytes: Vec<u8>,
+}
+
+impl Digest {
+    pub fn new(algorithm: HashAlgorithm, bytes: Vec<u8>) -> Self {
+        Digest { algorithm, bytes }
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub(crate) fn from_hex(algorithm: HashAlgorithm, hex: &str) -> Option<Self> {
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return None;
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Digest::new(algorithm, bytes))
+    }
+
+    /// lower case hex representation of the digest
+    pub fn to_hex(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+//formats as `<algorithm>:<hex>`, e.g. `sha256:9f86d0...`
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.to_hex())
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self)
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::new(Xxh3::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+            Hasher::Xxh3(hasher) => hasher.digest128().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+//streams the file through the hasher
+pub(crate) fn file_digest(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    cancelled: &AtomicBool,
+) -> Result<Digest, Error> {
+    let mut file = File::open(path).map_err(read_error(path))?;
+    let mut block = vec![0; COMPARE_BLOCK_SIZE];
+    let mut hasher = Hasher::new(algorithm);
+
+    loop {
+        let read = read_block(&mut file, &mut block, cancelled).map_err(read_error(path))?;
+        if read == 0 {
+            return Ok(Digest::new(algorithm, hasher.finalize()));
+        }
+        hasher.update(&block[..read]);
+    }
+}
+
+#[cfg(test)]
+mod tests_file_digest {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn known_digests() {
+        let dir = tempfile::Builder::new()
+            .prefix("file_digest_")
+            .tempdir()
+            .unwrap();
+        let file = dir.path().join("file");
+        fs::write(file.as_path(), b"test").unwrap();
+
+        let sha256 = file_digest(&file, HashAlgorithm::Sha256, &AtomicBool::new(false)).unwrap();
+        assert_eq!(
+            sha256.to_hex(),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+        assert_eq!(sha256.algorithm(), HashAlgorithm::Sha256);
+
+        let blake3 = file_digest(&file, HashAlgorithm::Blake3, &AtomicBool::new(false)).unwrap();
+        assert_eq!(blake3.as_bytes(), blake3::hash(b"test").as_bytes());
+
+        let xxh3 = file_digest(&file, HashAlgorithm::Xxh3, &AtomicBool::new(false)).unwrap();
+        assert_eq!(
+            xxh3.as_bytes(),
+            xxhash_rust::xxh3::xxh3_128(b"test").to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let digest = Digest::new(HashAlgorithm::Sha256, vec![0x00, 0x7f, 0xff]);
+        let parsed = Digest::from_hex(HashAlgorithm::Sha256, &digest.to_hex());
+        assert_eq!(parsed, Some(digest));
+        assert_eq!(Digest::from_hex(HashAlgorithm::Sha256, "0g"), None);
+    }
+
+    #[test]
+    fn display() {
+        let digest = Digest::new(HashAlgorithm::Blake3, vec![0x0a, 0xff]);
+        assert_eq!(digest.to_string(), "blake3:0aff");
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{Error, GlobFilter};
+
+//names of the ignore files read in every directory, later files take precedence
+pub(crate) const IGNORE_FILE_NAMES: [&str; 3] = [".gitignore", ".ignore", ".dircmpignore"];
+
+/// Which side's ignore files decide whether an entry is skipped.
+///
+/// The decision is made once per relative path and applied to both sides, so an entry is
+/// never skipped on one side only.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoreRules {
+    /// skip entries ignored by the files of either side
+    #[default]
+    Either,
+    /// skip entries only if the files of both sides ignore them
+    Both,
+    /// only the ignore files of the left tree apply
+    Left,
+    /// only the ignore files of the right tree apply
+    Right,
+}
+
+//the patterns of the ignore files of one directory
+#[derive(Debug)]
+struct IgnoreLevel {
+    //number of components of the directory relative to the root, the patterns are relative
+    //to it, so entries spelled differently on both sides match the same patterns
+    depth: usize,
+    filter: GlobFilter,
+}
+
+/// Ignore files of both sides which apply to the currently walked directory.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Ignores {
+    rules: Option<IgnoreRules>,
+    //outermost directory first
+    left: Vec<Arc<IgnoreLevel>>,
+    right: Vec<Arc<IgnoreLevel>>,
+}
+
+impl Ignores {
+    pub(crate) fn new(rules: Option<IgnoreRules>) -> Self {
+        Ignores {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.rules.is_some()
+    }
+
+    //returns the ignores for the directory at the relative path, adding its ignore files on
+    //the sides it exists on, the path may be spelled as on either side
+    pub(crate) fn enter(
+        &self,
+        left_dir: Option<&Path>,
+        right_dir: Option<&Path>,
+        relative: &Path,
+    ) -> Result<Self, Error> {
+        let rules = match self.rules {
+            Some(rules) => rules,
+            None => return Ok(self.clone()),
+        };
+        let mut ignores = self.clone();
+        if let (Some(dir), true) = (left_dir, rules != IgnoreRules::Right) {
+            push_level(&mut ignores.left, dir, relative)?;
+        }
+        if let (Some(dir), true) = (right_dir, rules != IgnoreRules::Left) {
+            push_level(&mut ignores.right, dir, relative)?;
+        }
+        Ok(ignores)
+    }
+
+    //true if the entry at the relative path is skipped on both sides
+    pub(crate) fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        let left = || is_ignored_by(&self.left, relative, is_dir);
+        let right = || is_ignored_by(&self.right, relative, is_dir);
+        match self.rules {
+            None => false,
+            Some(IgnoreRules::Either) => left() || right(),
+            Some(IgnoreRules::Both) => left() && right(),
+            Some(IgnoreRules::Left) => left(),
+            Some(IgnoreRules::Right) => right(),
+        }
+    }
+}
+
+fn push_level(
+    levels: &mut Vec<Arc<IgnoreLevel>>,
+    dir: &Path,
+    relative: &Path,
+) -> Result<(), Error> {
+    let mut lines = Vec::new();
+    for name in IGNORE_FILE_NAMES {
+        let path = dir.join(name);
+        match fs::read(&path) {
+            Ok(content) => lines.extend(
+                String::from_utf8_lossy(&content)
+                    .lines()
+                    .map(|line| line.to_string()),
+            ),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => return Err(Error::ReadFile { path, source }),
+        }
+    }
+    if !lines.is_empty() {
+        levels.push(Arc::new(IgnoreLevel {
+            depth: relative.components().count(),
+            filter: GlobFilter::new(lines)?,
+        }));
+    }
+    Ok(())
+}
+
+//the innermost ignore file with a matching pattern decides, like in git, all levels are
+//parents of the entry
+fn is_ignored_by(levels: &[Arc<IgnoreLevel>], relative: &Path, is_dir: bool) -> bool {
+    levels
+        .iter()
+        .rev()
+        .filter_map(|level| {
+            let path: PathBuf = relative.components().skip(level.depth).collect();
+            level.filter.matched(&path, is_dir)
+        })
+        .next()
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests_ignores {
+    use super::*;
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("ignores_")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn scoped_to_subtree() {
+        let dir = create_temp_dir();
+        fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/.ignore"), "!keep.log\n/local\n").unwrap();
+
+        let root = Ignores::new(Some(IgnoreRules::Left))
+            .enter(Some(dir.path()), None, Path::new(""))
+            .unwrap();
+        let sub = root
+            .enter(Some(&dir.path().join("sub")), None, Path::new("sub"))
+            .unwrap();
+
+        assert!(root.is_ignored(Path::new("keep.log"), false));
+        assert!(!sub.is_ignored(Path::new("sub/keep.log"), false));
+        assert!(sub.is_ignored(Path::new("sub/debug.log"), false));
+        assert!(sub.is_ignored(Path::new("sub/local"), true));
+        //the anchored pattern is relative to the directory of its ignore file
+        assert!(!sub.is_ignored(Path::new("sub/other/local"), true));
+    }
+
+    #[test]
+    fn rules() {
+        let left_dir = create_temp_dir();
+        fs::write(left_dir.path().join(".gitignore"), "left.txt\nboth.txt\n").unwrap();
+        let right_dir = create_temp_dir();
+        fs::write(
+            right_dir.path().join(".dircmpignore"),
+            "right.txt\nboth.txt\n",
+        )
+        .unwrap();
+
+        let ignored = |rules| {
+            let ignores = Ignores::new(Some(rules))
+                .enter(Some(left_dir.path()), Some(right_dir.path()), Path::new(""))
+                .unwrap();
+            ["left.txt", "right.txt", "both.txt"]
+                .map(|name| ignores.is_ignored(Path::new(name), false))
+        };
+
+        assert_eq!(ignored(IgnoreRules::Either), [true, true, true]);
+        assert_eq!(ignored(IgnoreRules::Both), [false, false, true]);
+        assert_eq!(ignored(IgnoreRules::Left), [true, false, true]);
+        assert_eq!(ignored(IgnoreRules::Right), [false, true, true]);
+    }
+}
@@ -1,152 +1,431 @@
+mod cache;
+mod compare;
+mod error;
 pub mod full;
+mod glob;
+mod hardlink;
+mod hash;
+mod ignore;
 pub mod light;
+mod metadata;
+mod names;
+mod parallel;
+mod predicate;
+mod special;
+mod walk;
+mod xattrs;
+
+pub use cache::DigestCache;
+pub use compare::{Check, CompareMode, FileCompDetails, FileCompResult};
+pub use error::Error;
+pub use glob::GlobFilter;
+pub use hardlink::HardLinkDiff;
+pub use hash::{Digest, HashAlgorithm};
+pub use ignore::IgnoreRules;
+pub use metadata::{MetadataChecks, MetadataDiff};
+pub use names::{NameDifference, NameMatching};
+pub use predicate::MetadataFilter;
+pub use special::{DeviceNumber, EntryDetails, SpecialFiles};
+pub use xattrs::{XattrChecks, XattrDiff};
 
 //use log::debug;
-use regex::Regex;
+use regex::bytes::Regex;
+use std::cmp::Ordering;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
-use std::{io, path::PathBuf};
-
-#[derive(Debug, Clone)]
-pub enum Filter {
-    Exclude(Vec<Regex>),
-    Include(Vec<Regex>),
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ignore::Ignores;
+
+/// Patterns deciding which entries are compared, matched against the path of an entry
+/// relative to the compared root.
+///
+/// An entry is skipped if it matches any `exclude` expression or is excluded by the `glob`
+/// patterns. Otherwise, if `include` expressions are given, a file is skipped unless it
+/// matches any of them. Exclusion is checked first, so it always wins over inclusion.
+/// Entries passing the patterns are skipped if they do not satisfy the `metadata` predicates.
+///
+/// Include expressions and metadata predicates are not applied to directories, they are
+/// always walked since they could contain a matching file. Directories skipped by an
+/// exclusion are not read at all. The expressions match the raw bytes of the path,
+/// so names which are not valid UTF-8 can be filtered as well.
+#[derive(Debug, Clone, Default)]
+pub struct FilterSet {
+    /// regular expressions, files not matching any of them are skipped
+    pub include: Vec<Regex>,
+    /// regular expressions, entries matching any of them are skipped
+    pub exclude: Vec<Regex>,
+    /// gitignore style patterns, see [`GlobFilter`]
+    pub glob: Option<GlobFilter>,
+    /// predicates over the size, modification time, kind and permissions of an entry
+    pub metadata: Option<MetadataFilter>,
 }
-//returns true if the path should be filtered out
-fn apply_filter(path: &str, filter_opt: &Option<Filter>) -> bool {
-    if let Some(filter) = filter_opt {
-        match filter {
-            Filter::Exclude(pattern_list) => {
-                for pattern in pattern_list {
-                    if pattern.is_match(path) {
-                        return true;
-                    }
-                }
-            }
-            Filter::Include(pattern_list) => {
-                for pattern in pattern_list {
-                    if !pattern.is_match(path) {
-                        return true;
-                    }
-                }
+
+impl FilterSet {
+    //returns true if the path should be filtered out
+    fn is_filtered(&self, path: &Path, is_dir: bool) -> bool {
+        if let Some(glob_filter) = &self.glob {
+            if glob_filter.is_excluded(path, is_dir) {
+                return true;
             }
         }
+        let path = path.as_os_str().as_encoded_bytes();
+        if self.exclude.iter().any(|pattern| pattern.is_match(path)) {
+            return true;
+        }
+        //a directory could contain matching files, whatever its own name is
+        if is_dir || self.include.is_empty() {
+            return false;
+        }
+        !self.include.iter().any(|pattern| pattern.is_match(path))
+    }
+
+    //only glob and include patterns distinguish directories, so the others do not need a stat
+    fn needs_kind(&self) -> bool {
+        self.glob.is_some() || !self.include.is_empty()
     }
-    //default if no filter values are provided
-    false
 }
 
-#[cfg(test)]
-mod tests_apply_filter {
-    use super::*;
+/// The roots of a comparison, shared by all of its entries.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Roots {
+    pub left: PathBuf,
+    pub right: PathBuf,
+}
 
-    // fn init() {
-    //     let _ = env_logger::builder().is_test(true).try_init();
-    // }
+impl Roots {
+    pub(crate) fn left_entry(self: &Arc<Self>, left_path: &Path) -> EntryPath {
+        let relative = left_path.strip_prefix(&self.left).unwrap();
+        EntryPath::new(Arc::clone(self), relative.to_path_buf())
+    }
 
-    #[test]
-    fn empty() {
-        let path = ".git/config";
-        let filter = Some(Filter::Include(Vec::new()));
+    pub(crate) fn right_entry(self: &Arc<Self>, right_path: &Path) -> EntryPath {
+        let relative = right_path.strip_prefix(&self.right).unwrap();
+        EntryPath::new(Arc::clone(self), relative.to_path_buf())
+    }
 
-        assert!(!apply_filter(path, &filter));
+    //entry existing on both sides, which may be spelled differently on the right
+    pub(crate) fn pair_entry(self: &Arc<Self>, left_path: &Path, right_path: &Path) -> EntryPath {
+        let mut path = self.left_entry(left_path);
+        let right_relative = right_path.strip_prefix(&self.right).unwrap();
+        if right_relative != path.relative {
+            path.right_relative = Some(right_relative.to_path_buf());
+        }
+        path
     }
+}
 
-    #[test]
-    fn none() {
-        let path = ".git/config";
-        let filter = None;
+/// The path of an entry relative to both roots of the comparison.
+///
+/// Entries are compared and ordered by their relative path first. If names are matched
+/// by `Options::name_matching`, an entry existing on both sides can be spelled differently
+/// on the right, then both spellings are kept.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryPath {
+    relative: PathBuf,
+    //only set if it differs from `relative`
+    right_relative: Option<PathBuf>,
+    roots: Arc<Roots>,
+}
 
-        assert!(!apply_filter(path, &filter));
+impl EntryPath {
+    pub fn new(roots: Arc<Roots>, relative: PathBuf) -> Self {
+        EntryPath {
+            relative,
+            right_relative: None,
+            roots,
+        }
     }
 
-    #[test]
-    fn include() {
-        let path = "src/main.rs";
-        let regex = Regex::new(r".rs").unwrap();
-        let filter = Some(Filter::Include(vec![regex]));
+    /// path relative to the roots, spelled as on the left if the entry exists there
+    pub fn relative(&self) -> &Path {
+        &self.relative
+    }
 
-        assert!(!apply_filter(path, &filter));
+    /// path relative to the right root, spelled as on the right
+    pub fn right_relative(&self) -> &Path {
+        self.right_relative.as_deref().unwrap_or(&self.relative)
     }
 
-    #[test]
-    fn exclude() {
-        let path = ".git/config";
-        let regex = Regex::new(".git").unwrap();
-        let filter = Some(Filter::Exclude(vec![regex]));
+    /// true if the entry exists on both sides, but its own name only matched by
+    /// `Options::name_matching`, parent directories spelled differently are reported
+    /// themselves
+    pub fn spellings_differ(&self) -> bool {
+        self.right_name().is_some()
+    }
+
+    /// how the spellings of the entry's own name differ, if they do
+    pub fn name_difference(&self) -> Option<NameDifference> {
+        let right_name = self.right_name()?;
+        Some(NameDifference::between(
+            self.relative.file_name().unwrap_or_default(),
+            right_name,
+        ))
+    }
+
+    //the last component of the right spelling, only if it differs from the left
+    fn right_name(&self) -> Option<&OsStr> {
+        let right_name = self.right_relative.as_ref()?.file_name()?;
+        (self.relative.file_name() != Some(right_name)).then_some(right_name)
+    }
+
+    pub fn roots(&self) -> &Roots {
+        &self.roots
+    }
+
+    /// absolute path below the left root, which only exists if the entry exists on the left
+    pub fn left(&self) -> PathBuf {
+        self.roots.left.join(&self.relative)
+    }
+
+    /// absolute path below the right root, which only exists if the entry exists on the right
+    pub fn right(&self) -> PathBuf {
+        self.roots.right.join(self.right_relative())
+    }
+}
 
-        assert!(apply_filter(path, &filter));
+//the roots are the same for all entries of a comparison, so only the relative path is shown,
+//followed by the spelling on the right if it differs
+impl fmt::Debug for EntryPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.relative)?;
+        match &self.right_relative {
+            Some(right_relative) => write!(f, " ~ {:?}", right_relative),
+            None => Ok(()),
+        }
     }
 }
 
+/// The sides of the comparison an entry exists on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Sides {
+    Both,
+    Left,
+    Right,
+}
+
+/// Why a directory was not descended into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Unexplored {
+    /// the directory is at `Options::max_depth`
+    DepthLimit,
+    /// the directory is on another file system than the root of its side, see
+    /// `Options::one_file_system`
+    MountPoint,
+    /// the directory was reached through a followed symlink leading back to a directory
+    /// which is being walked, see `SymlinkMode::Follow`
+    SymlinkCycle,
+}
+
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum EitherOrBoth {
+    Both(EntryPath),
+    /// an entry existing on the left only, with its type
+    Left(EntryPath, EntryDetails),
+    /// an entry existing on the right only, with its type
+    Right(EntryPath, EntryDetails),
+    /// the same name is used for different kinds of entries, e.g. a file and a directory
+    TypeMismatch {
+        path: EntryPath,
+        left_kind: EntryKind,
+        right_kind: EntryKind,
+    },
+    /// a directory which was not descended into, so its contents are unknown
+    Unexplored {
+        path: EntryPath,
+        sides: Sides,
+        reason: Unexplored,
+    },
+}
+
+impl EitherOrBoth {
+    pub fn path(&self) -> &EntryPath {
+        match self {
+            EitherOrBoth::Both(path)
+            | EitherOrBoth::Left(path, _)
+            | EitherOrBoth::Right(path, _)
+            | EitherOrBoth::TypeMismatch { path, .. }
+            | EitherOrBoth::Unexplored { path, .. } => path,
+        }
+    }
+}
+
+//entries of two directories with the same name
+#[derive(Debug, PartialEq, Eq)]
+enum ZipEntry {
     Both(PathBuf, PathBuf),
     Left(PathBuf),
     Right(PathBuf),
 }
 
-fn zip_dir_entries(
-    left_dir: &PathBuf,
-    right_dir: &PathBuf,
-    left_base: &str,
-    right_base: &str,
-    filter: &Option<Filter>,
-) -> io::Result<Vec<EitherOrBoth>> {
-    let left_read_dir = fs::read_dir(left_dir)?;
-    let right_read_dir = fs::read_dir(right_dir)?;
-
-    let left_entries = left_read_dir
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()
-        .expect("some error with left dir");
-
-    let right_entries = right_read_dir
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()
-        .expect("some error with right dir");
-
-    // The order in which `read_dir` returns entries is not guaranteed. If reproducible
-    // ordering is required the entries should be explicitly sorted.
-
-    //left_entries.sort();
-    //right_entries.sort();
-    let mut results: Vec<EitherOrBoth> = Vec::new();
-
-    for left_entry in &left_entries {
-        //debug!("left entry: {:?}", left_entry);
-        let left_short_path = left_entry.strip_prefix(left_base).unwrap();
-        if !apply_filter(left_short_path.to_str().unwrap(), filter) {
-            let mut found_match = None;
-            for right_entry in &right_entries {
-                let right_short_path = right_entry.strip_prefix(right_base).unwrap();
-                if left_short_path == right_short_path {
-                    found_match = Some(EitherOrBoth::Both(
-                        left_entry.to_owned(),
-                        right_entry.to_owned(),
-                    ));
-                }
-            }
+/// The kind of a directory entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EntryKind {
+    File,
+    Dir,
+    /// a symlink which is not followed, see [`SymlinkMode`]
+    Symlink,
+    /// a named pipe
+    Fifo,
+    /// a unix domain socket
+    Socket,
+    BlockDevice,
+    CharDevice,
+    /// anything else
+    Other,
+}
 
-            match found_match {
-                Some(both) => results.push(both),
-                None => results.push(EitherOrBoth::Left(left_entry.to_owned())),
-            }
+/// How symlinks inside the compared directories are handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkMode {
+    /// skip all symlinks
+    #[default]
+    Ignore,
+    /// compare symlinks by their target paths without following them
+    Target,
+    /// follow symlinks and compare the entries they point to, symlinks leading back into an
+    /// already walked directory are not descended into and dangling symlinks are compared
+    /// by their targets
+    Follow,
+}
+
+/// How an entry was reached through a symlink.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymlinkKind {
+    /// a symlink compared by its target, which exists
+    Link(PathBuf),
+    /// a symlink which was followed to its target
+    Followed(PathBuf),
+    /// a symlink whose target can not be resolved
+    Dangling(PathBuf),
+}
+
+impl SymlinkKind {
+    /// the target path stored in the symlink
+    pub fn target(&self) -> &Path {
+        match self {
+            SymlinkKind::Link(target)
+            | SymlinkKind::Followed(target)
+            | SymlinkKind::Dangling(target) => target,
         }
     }
+}
 
-    for right_entry in &right_entries {
-        let right_short_path = right_entry.strip_prefix(right_base).unwrap();
-        if !apply_filter(right_short_path.to_str().unwrap(), filter) {
-            let mut found_match = None;
-            for left_entry in &left_entries {
-                let left_short_path = left_entry.strip_prefix(left_base).unwrap();
-                if left_short_path == right_short_path {
-                    found_match = Some(());
-                }
-            }
+fn kind_of(file_type: fs::FileType) -> EntryKind {
+    if file_type.is_file() {
+        EntryKind::File
+    } else if file_type.is_dir() {
+        EntryKind::Dir
+    } else if file_type.is_symlink() {
+        EntryKind::Symlink
+    } else {
+        special_kind_of(file_type)
+    }
+}
+
+#[cfg(unix)]
+fn special_kind_of(file_type: fs::FileType) -> EntryKind {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        EntryKind::Fifo
+    } else if file_type.is_socket() {
+        EntryKind::Socket
+    } else if file_type.is_block_device() {
+        EntryKind::BlockDevice
+    } else if file_type.is_char_device() {
+        EntryKind::CharDevice
+    } else {
+        EntryKind::Other
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind_of(_file_type: fs::FileType) -> EntryKind {
+    EntryKind::Other
+}
 
-            if found_match.is_none() {
-                results.push(EitherOrBoth::Right(right_entry.to_owned()));
+//returns the kind of the entry and, if it is a symlink, how it was handled
+fn classify_entry(
+    path: &Path,
+    mode: SymlinkMode,
+) -> Result<(EntryKind, Option<SymlinkKind>), Error> {
+    let read_file_error = |source| Error::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let file_type = fs::symlink_metadata(path)
+        .map_err(read_file_error)?
+        .file_type();
+    if !file_type.is_symlink() {
+        return Ok((kind_of(file_type), None));
+    }
+
+    let target = fs::read_link(path).map_err(read_file_error)?;
+    //following the symlink fails for missing targets as well as for symlink loops
+    let followed = fs::metadata(path);
+    match (mode, followed) {
+        (SymlinkMode::Follow, Ok(metadata)) => Ok((
+            kind_of(metadata.file_type()),
+            Some(SymlinkKind::Followed(target)),
+        )),
+        (_, Ok(_)) => Ok((EntryKind::Symlink, Some(SymlinkKind::Link(target)))),
+        (_, Err(_)) => Ok((EntryKind::Symlink, Some(SymlinkKind::Dangling(target)))),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn zip_dir_entries(
+    left_dir: &Path,
+    right_dir: &Path,
+    left_base: &Path,
+    right_base: &Path,
+    left_filters: &[FilterSet],
+    right_filters: &[FilterSet],
+    ignores: &Ignores,
+    matching: &NameMatching,
+) -> Result<Vec<ZipEntry>, Error> {
+    let left_entries = filter_entries(read_dir_paths(left_dir)?, left_base, left_filters, ignores);
+    let right_entries = filter_entries(
+        read_dir_paths(right_dir)?,
+        right_base,
+        right_filters,
+        ignores,
+    );
+
+    //both listings are sorted by the keys their names are matched by, so they can be merged
+    //in a single pass
+    let mut left_iter = keyed_entries(left_entries, matching).into_iter().peekable();
+    let mut right_iter = keyed_entries(right_entries, matching)
+        .into_iter()
+        .peekable();
+    let mut results: Vec<ZipEntry> = Vec::new();
+
+    loop {
+        let order = match (left_iter.peek(), right_iter.peek()) {
+            (Some((left_key, _)), Some((right_key, _))) => left_key.cmp(right_key),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => break,
+        };
+
+        match order {
+            Ordering::Less => results.push(ZipEntry::Left(left_iter.next().unwrap().1)),
+            Ordering::Greater => results.push(ZipEntry::Right(right_iter.next().unwrap().1)),
+            Ordering::Equal => {
+                //several names of one side can share a key, e.g. `README` and `readme`
+                let key = left_iter.peek().unwrap().0.clone();
+                let group = |iter: &mut std::iter::Peekable<_>| {
+                    std::iter::from_fn(|| iter.next_if(|(entry_key, _)| *entry_key == key))
+                        .map(|(_, entry)| entry)
+                        .collect::<Vec<PathBuf>>()
+                };
+                let left_group = group(&mut left_iter);
+                let right_group = group(&mut right_iter);
+                pair_entries(left_group, right_group, &mut results);
             }
         }
     }
@@ -154,6 +433,273 @@ fn zip_dir_entries(
     Ok(results)
 }
 
+//pairs each entry with the key its name is matched by, sorted by key and name
+fn keyed_entries(entries: Vec<PathBuf>, matching: &NameMatching) -> Vec<(OsString, PathBuf)> {
+    let mut keyed: Vec<_> = entries
+        .into_iter()
+        .map(|entry| (matching.key(entry.file_name().unwrap()), entry))
+        .collect();
+    keyed.sort();
+    keyed
+}
+
+//pairs entries whose names share a key, exactly equal names first and the others in order,
+//the remaining entries exist on one side only
+fn pair_entries(
+    left_group: Vec<PathBuf>,
+    mut right_group: Vec<PathBuf>,
+    results: &mut Vec<ZipEntry>,
+) {
+    let mut unpaired = Vec::new();
+    for left_entry in left_group {
+        let exact = right_group
+            .iter()
+            .position(|right_entry| right_entry.file_name() == left_entry.file_name());
+        match exact {
+            Some(index) => results.push(ZipEntry::Both(left_entry, right_group.remove(index))),
+            None => unpaired.push(left_entry),
+        }
+    }
+    let mut right_group = right_group.into_iter();
+    for left_entry in unpaired {
+        match right_group.next() {
+            Some(right_entry) => results.push(ZipEntry::Both(left_entry, right_entry)),
+            None => results.push(ZipEntry::Left(left_entry)),
+        }
+    }
+    results.extend(right_group.map(ZipEntry::Right));
+}
+
+//drops all entries whose path relative to the base is filtered out by any of the filters or
+//ignored, so skipped directories are never read
+fn filter_entries(
+    entries: Vec<PathBuf>,
+    base: &Path,
+    filters: &[FilterSet],
+    ignores: &Ignores,
+) -> Vec<PathBuf> {
+    let needs_kind = filters.iter().any(FilterSet::needs_kind) || ignores.is_active();
+    entries
+        .into_iter()
+        .filter(|entry| {
+            //like git, symlinks to directories are not treated as directories
+            let is_dir = needs_kind
+                && fs::symlink_metadata(entry)
+                    .map(|metadata| metadata.is_dir())
+                    .unwrap_or(false);
+            let relative = entry.strip_prefix(base).unwrap();
+            !filters
+                .iter()
+                .any(|filter| filter.is_filtered(relative, is_dir))
+                && !ignores.is_ignored(relative, is_dir)
+        })
+        .collect()
+}
+
+//returns the entries of a directory sorted by name
+fn read_dir_paths(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let read_dir_error = |source| Error::ReadDir {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut entries = fs::read_dir(path)
+        .map_err(read_dir_error)?
+        .map(|res| res.map(|e| e.path()).map_err(read_dir_error))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // The order in which `read_dir` returns entries is not guaranteed, sort them to get
+    // reproducible results.
+    entries.sort();
+    Ok(entries)
+}
+
+//returns an error if the given root of a comparison is missing or not a directory
+fn check_root(path: &Path) -> Result<(), Error> {
+    if !path.exists() {
+        return Err(Error::RootNotFound(path.to_path_buf()));
+    }
+
+    if !path.is_dir() {
+        return Err(Error::RootNotDirectory(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub ignore_equal: bool,
+    pub ignore_left_only: bool,
+    pub ignore_right_only: bool,
+    /// filter applied to both trees
+    pub filter: Option<FilterSet>,
+    /// filter applied to the left tree only, in addition to `filter`
+    pub left_filter: Option<FilterSet>,
+    /// filter applied to the right tree only, in addition to `filter`
+    pub right_filter: Option<FilterSet>,
+    /// descend into sub directories, honored by both `full` and `light`, otherwise pairs of
+    /// directories are reported without comparing their contents
+    pub recursive: bool,
+    /// number of threads comparing file contents, values of 0 or 1 compare serially
+    pub threads: usize,
+    /// checks deciding whether two files are equal, used by `full::compare_dirs`
+    pub compare_mode: CompareMode,
+    /// algorithm used by `compare_mode.hash`
+    pub hash_algorithm: HashAlgorithm,
+    /// cache for the digests of `compare_mode.hash`, shared by all worker threads
+    pub digest_cache: Option<Arc<DigestCache>>,
+    /// how symlinks inside the compared directories are handled
+    pub symlinks: SymlinkMode,
+    /// metadata attributes compared in addition to `compare_mode`, used by `full::compare_dirs`
+    pub metadata_checks: MetadataChecks,
+    /// extended attributes compared in addition to `compare_mode` if set, used by
+    /// `full::compare_dirs`
+    pub xattrs: Option<XattrChecks>,
+    /// detect hard linked files on both sides, used by `full::compare_dirs`
+    pub hard_links: bool,
+    /// skip entries ignored by `.gitignore`, `.ignore` and `.dircmpignore` files inside the
+    /// compared trees if set, the rules decide whose files apply
+    pub ignore_files: Option<IgnoreRules>,
+    /// deepest level of reported entries if `recursive`, the entries of the roots are at
+    /// depth 1, directories at this depth are reported as unexplored
+    pub max_depth: Option<usize>,
+    /// entries above this depth are walked, but not reported
+    pub min_depth: usize,
+    /// do not descend into directories on another device than the root of their side,
+    /// like `find -xdev`, they are reported as unexplored mount points
+    pub one_file_system: bool,
+    /// how names are matched between both trees, byte by byte by default
+    pub name_matching: NameMatching,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            ignore_equal: false,
+            ignore_left_only: false,
+            ignore_right_only: false,
+            filter: None,
+            left_filter: None,
+            right_filter: None,
+            recursive: false,
+            threads: 1,
+            compare_mode: CompareMode::default(),
+            hash_algorithm: HashAlgorithm::default(),
+            digest_cache: None,
+            symlinks: SymlinkMode::default(),
+            metadata_checks: MetadataChecks::default(),
+            xattrs: None,
+            hard_links: false,
+            ignore_files: None,
+            max_depth: None,
+            min_depth: 0,
+            one_file_system: false,
+            name_matching: NameMatching::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_filter_set {
+    use super::*;
+
+    // fn init() {
+    //     let _ = env_logger::builder().is_test(true).try_init();
+    // }
+
+    #[test]
+    fn empty() {
+        let path = Path::new(".git/config");
+        let filter = FilterSet::default();
+
+        assert!(!filter.is_filtered(path, false));
+    }
+
+    #[test]
+    fn include() {
+        let path = Path::new("src/main.rs");
+        let regex = Regex::new(r".rs").unwrap();
+        let filter = FilterSet {
+            include: vec![regex],
+            ..Default::default()
+        };
+
+        assert!(!filter.is_filtered(path, false));
+        assert!(filter.is_filtered(Path::new("Cargo.toml"), false));
+    }
+
+    #[test]
+    fn include_any() {
+        let filter = FilterSet {
+            include: vec![
+                Regex::new(r"\.rs$").unwrap(),
+                Regex::new(r"\.toml$").unwrap(),
+            ],
+            ..Default::default()
+        };
+
+        assert!(!filter.is_filtered(Path::new("src/main.rs"), false));
+        assert!(!filter.is_filtered(Path::new("Cargo.toml"), false));
+        assert!(filter.is_filtered(Path::new("README.md"), false));
+    }
+
+    #[test]
+    fn include_dirs() {
+        let filter = FilterSet {
+            include: vec![Regex::new(r"\.rs$").unwrap()],
+            ..Default::default()
+        };
+
+        //directories are walked to find matching files
+        assert!(!filter.is_filtered(Path::new("src"), true));
+        assert!(filter.is_filtered(Path::new("src"), false));
+    }
+
+    #[test]
+    fn exclude() {
+        let path = Path::new(".git/config");
+        let regex = Regex::new(".git").unwrap();
+        let filter = FilterSet {
+            exclude: vec![regex],
+            ..Default::default()
+        };
+
+        assert!(filter.is_filtered(path, false));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = FilterSet {
+            include: vec![Regex::new(r"\.(rs|toml)$").unwrap()],
+            exclude: vec![Regex::new(r"^target/").unwrap()],
+            glob: Some(GlobFilter::new(["generated.rs"]).unwrap()),
+            metadata: None,
+        };
+
+        assert!(!filter.is_filtered(Path::new("Cargo.toml"), false));
+        assert!(filter.is_filtered(Path::new("target/build.rs"), false));
+        assert!(filter.is_filtered(Path::new("src/generated.rs"), false));
+        assert!(filter.is_filtered(Path::new("README.md"), false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"share/caf\xe9.txt"));
+        let regex = Regex::new(r"(?-u)\xe9\.txt$").unwrap();
+        let filter = FilterSet {
+            exclude: vec![regex],
+            ..Default::default()
+        };
+
+        assert!(filter.is_filtered(path, false));
+    }
+}
+
 #[cfg(test)]
 mod tests_zip_dir_entries {
     use super::*;
@@ -174,23 +720,26 @@ mod tests_zip_dir_entries {
     fn emtpy() {
         init();
         let left_dir = create_temp_dir();
-        let left_path_buf = left_dir.into_path();
-        let left_base = left_path_buf.to_str().unwrap();
+        let left_path_buf = left_dir.keep();
+        let left_base = left_path_buf.as_path();
 
         let right_dir = create_temp_dir();
-        let right_path_buf = right_dir.into_path();
-        let right_base = right_path_buf.to_str().unwrap();
+        let right_path_buf = right_dir.keep();
+        let right_base = right_path_buf.as_path();
 
         let result = zip_dir_entries(
             &left_path_buf,
             &right_path_buf,
             left_base,
             right_base,
-            &None,
+            &[],
+            &[],
+            &Ignores::default(),
+            &NameMatching::default(),
         )
         .unwrap();
 
-        assert_eq!(result, Vec::<EitherOrBoth>::new());
+        assert_eq!(result, Vec::<ZipEntry>::new());
     }
 
     #[test]
@@ -199,25 +748,28 @@ mod tests_zip_dir_entries {
         let left_dir = create_temp_dir();
         let left_file = left_dir.path().join("file1");
         fs::write(left_file.as_path(), b"Hello, world!").unwrap();
-        let left_path_buf = left_dir.into_path();
-        let left_base = left_path_buf.to_str().unwrap();
+        let left_path_buf = left_dir.keep();
+        let left_base = left_path_buf.as_path();
 
         let right_dir = create_temp_dir();
         let right_file = right_dir.path().join("file1");
         fs::write(right_file.as_path(), b"Hello, world!").unwrap();
-        let right_path_buf = right_dir.into_path();
-        let right_base = right_path_buf.to_str().unwrap();
+        let right_path_buf = right_dir.keep();
+        let right_base = right_path_buf.as_path();
 
         let result = zip_dir_entries(
             &left_path_buf,
             &right_path_buf,
             left_base,
             right_base,
-            &None,
+            &[],
+            &[],
+            &Ignores::default(),
+            &NameMatching::default(),
         )
         .unwrap();
 
-        assert_eq!(result, vec![EitherOrBoth::Both(left_file, right_file)]);
+        assert_eq!(result, vec![ZipEntry::Both(left_file, right_file)]);
     }
 
     #[test]
@@ -228,19 +780,28 @@ mod tests_zip_dir_entries {
         fs::create_dir(left_sub_dir.as_path()).unwrap();
         let left_file = left_sub_dir.as_path().join("file1");
         fs::write(left_file.as_path(), b"Hello, world!").unwrap();
-        let left_base = left_dir.path().to_str().unwrap();
+        let left_base = left_dir.path();
 
         let right_dir = create_temp_dir();
         let right_sub_dir = right_dir.path().join("subdir");
         fs::create_dir(right_sub_dir.as_path()).unwrap();
         let right_file = right_sub_dir.as_path().join("file1");
         fs::write(right_file.as_path(), b"Hello, world!").unwrap();
-        let right_base = right_dir.path().to_str().unwrap();
+        let right_base = right_dir.path();
 
-        let result =
-            zip_dir_entries(&left_sub_dir, &right_sub_dir, left_base, right_base, &None).unwrap();
+        let result = zip_dir_entries(
+            &left_sub_dir,
+            &right_sub_dir,
+            left_base,
+            right_base,
+            &[],
+            &[],
+            &Ignores::default(),
+            &NameMatching::default(),
+        )
+        .unwrap();
 
-        assert_eq!(result, vec![EitherOrBoth::Both(left_file, right_file)]);
+        assert_eq!(result, vec![ZipEntry::Both(left_file, right_file)]);
     }
     #[test]
     fn left() {
@@ -248,98 +809,185 @@ mod tests_zip_dir_entries {
         let left_dir = create_temp_dir();
         let left_file = left_dir.path().join("file1");
         fs::write(left_file.as_path(), b"Hello, world!").unwrap();
-        let left_path_buf = left_dir.into_path();
-        let left_base = left_path_buf.to_str().unwrap();
+        let left_path_buf = left_dir.keep();
+        let left_base = left_path_buf.as_path();
 
         let right_dir = create_temp_dir();
-        let right_path_buf = right_dir.into_path();
-        let right_base = right_path_buf.to_str().unwrap();
+        let right_path_buf = right_dir.keep();
+        let right_base = right_path_buf.as_path();
 
         let result = zip_dir_entries(
             &left_path_buf,
             &right_path_buf,
             left_base,
             right_base,
-            &None,
+            &[],
+            &[],
+            &Ignores::default(),
+            &NameMatching::default(),
         )
         .unwrap();
-        assert_eq!(result, vec![EitherOrBoth::Left(left_file)]);
+        assert_eq!(result, vec![ZipEntry::Left(left_file)]);
     }
     #[test]
     fn right() {
         init();
         let left_dir = create_temp_dir();
-        let left_path_buf = left_dir.into_path();
-        let left_base = left_path_buf.to_str().unwrap();
+        let left_path_buf = left_dir.keep();
+        let left_base = left_path_buf.as_path();
 
         let right_dir = create_temp_dir();
         let right_file = right_dir.path().join("file1");
         fs::write(right_file.as_path(), b"Hello, world!").unwrap();
-        let right_path_buf = right_dir.into_path();
-        let right_base = right_path_buf.to_str().unwrap();
+        let right_path_buf = right_dir.keep();
+        let right_base = right_path_buf.as_path();
 
         let result = zip_dir_entries(
             &left_path_buf,
             &right_path_buf,
             left_base,
             right_base,
-            &None,
+            &[],
+            &[],
+            &Ignores::default(),
+            &NameMatching::default(),
         )
         .unwrap();
 
-        assert_eq!(result, vec![EitherOrBoth::Right(right_file)]);
+        assert_eq!(result, vec![ZipEntry::Right(right_file)]);
     }
-}
 
-fn list_files(path: &PathBuf) -> Vec<PathBuf> {
-    let mut result: Vec<PathBuf> = Vec::new();
-
-    let read_dir = fs::read_dir(path).unwrap();
-
-    let dir_entries = read_dir
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()
-        .expect("some error with left dir");
-    for entry in dir_entries {
-        if entry.is_dir() {
-            //get elements from sub dirs
-            let mut subtree_results = list_files(&entry);
-            result.append(&mut subtree_results);
-            continue;
+    #[test]
+    fn sorted_merge() {
+        init();
+        let left_dir = create_temp_dir();
+        let right_dir = create_temp_dir();
+        for name in ["d", "b", "a"] {
+            fs::write(left_dir.path().join(name), b"Hello, world!").unwrap();
         }
-        if entry.is_file() {
-            result.push(entry);
-            continue;
+        for name in ["e", "c", "b"] {
+            fs::write(right_dir.path().join(name), b"Hello, world!").unwrap();
         }
-        if entry.is_symlink() {
-            //ignore
-            continue;
+        let left_base = left_dir.path();
+        let right_base = right_dir.path();
+
+        let result = zip_dir_entries(
+            left_dir.path(),
+            right_dir.path(),
+            left_base,
+            right_base,
+            &[],
+            &[],
+            &Ignores::default(),
+            &NameMatching::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                ZipEntry::Left(left_dir.path().join("a")),
+                ZipEntry::Both(left_dir.path().join("b"), right_dir.path().join("b")),
+                ZipEntry::Right(right_dir.path().join("c")),
+                ZipEntry::Left(left_dir.path().join("d")),
+                ZipEntry::Right(right_dir.path().join("e")),
+            ]
+        );
+    }
+
+    #[test]
+    fn case_insensitive() {
+        init();
+        let left_dir = create_temp_dir();
+        let right_dir = create_temp_dir();
+        for name in ["readme", "README", "Makefile"] {
+            fs::write(left_dir.path().join(name), b"Hello, world!").unwrap();
+        }
+        for name in ["makefile", "readme"] {
+            fs::write(right_dir.path().join(name), b"Hello, world!").unwrap();
         }
+        let left_base = left_dir.path();
+        let right_base = right_dir.path();
+        let matching = NameMatching {
+            case_insensitive: true,
+            ..Default::default()
+        };
+
+        let result = zip_dir_entries(
+            left_dir.path(),
+            right_dir.path(),
+            left_base,
+            right_base,
+            &[],
+            &[],
+            &Ignores::default(),
+            &matching,
+        )
+        .unwrap();
+
+        //exactly equal names are paired first
+        assert_eq!(
+            result,
+            vec![
+                ZipEntry::Both(
+                    left_dir.path().join("Makefile"),
+                    right_dir.path().join("makefile")
+                ),
+                ZipEntry::Both(
+                    left_dir.path().join("readme"),
+                    right_dir.path().join("readme")
+                ),
+                ZipEntry::Left(left_dir.path().join("README")),
+            ]
+        );
     }
-    result
 }
 
-#[derive(Debug, Clone)]
-pub struct Options {
-    pub ignore_equal: bool,
-    pub ignore_left_only: bool,
-    pub ignore_right_only: bool,
-    pub filter: Option<Filter>,
-    pub recursive: bool,
-}
+#[cfg(test)]
+mod tests_entry_path {
+    use super::*;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub enum FileCompResult {
-    Equal,
-    Different,
-}
-fn compare_two_files(left_path: &PathBuf, right_path: &PathBuf) -> io::Result<FileCompResult> {
-    let left_file = fs::read(left_path)?;
-    let right_file = fs::read(right_path)?;
+    #[test]
+    fn resolves_both_sides() {
+        let roots = Arc::new(Roots {
+            left: PathBuf::from("/left"),
+            right: PathBuf::from("/right"),
+        });
+        let path = roots.right_entry(Path::new("/right/sub/file.txt"));
+        assert_eq!(path.relative(), Path::new("sub/file.txt"));
+        assert_eq!(path.left(), PathBuf::from("/left/sub/file.txt"));
+        assert_eq!(path.right(), PathBuf::from("/right/sub/file.txt"));
+        assert_eq!(format!("{:?}", path), "\"sub/file.txt\"");
+    }
 
-    if left_file == right_file {
-        Ok(FileCompResult::Equal)
-    } else {
-        Ok(FileCompResult::Different)
+    #[test]
+    fn different_spellings() {
+        let roots = Arc::new(Roots {
+            left: PathBuf::from("/left"),
+            right: PathBuf::from("/right"),
+        });
+        let path = roots.pair_entry(
+            Path::new("/left/Sub/README.md"),
+            Path::new("/right/sub/Readme.md"),
+        );
+        assert!(path.spellings_differ());
+        assert_eq!(path.name_difference(), Some(NameDifference::Case));
+        assert_eq!(path.relative(), Path::new("Sub/README.md"));
+        assert_eq!(path.right_relative(), Path::new("sub/Readme.md"));
+        assert_eq!(path.right(), PathBuf::from("/right/sub/Readme.md"));
+        assert_eq!(
+            format!("{:?}", path),
+            "\"Sub/README.md\" ~ \"sub/Readme.md\""
+        );
+
+        let path = roots.pair_entry(Path::new("/left/file"), Path::new("/right/file"));
+        assert!(!path.spellings_differ());
+        assert_eq!(path.name_difference(), None);
+
+        //only the parent directory is spelled differently
+        let path = roots.pair_entry(Path::new("/left/Sub/file"), Path::new("/right/sub/file"));
+        assert!(!path.spellings_differ());
+        assert_eq!(path.name_difference(), None);
+        assert_eq!(path.right(), PathBuf::from("/right/sub/file"));
     }
 }
@@ -1,109 +1,104 @@
-use log::{debug, error, trace};
-
-use std::io;
 use std::path::Path;
 
-use crate::{list_files, zip_dir_entries, EitherOrBoth, Options};
+use crate::full::{self, DirCmpEntry};
+use crate::{CompareMode, EitherOrBoth, Error, MetadataChecks, Options};
+
+/// Lazy iterator over the differences of two directory trees, see [`compare_dirs_iter`].
+pub struct CompareDirsIter {
+    inner: full::CompareDirsIter,
+}
+
+impl Iterator for CompareDirsIter {
+    type Item = Result<EitherOrBoth, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.inner.next()? {
+            Ok(DirCmpEntry::Both(path, _, _)) => EitherOrBoth::Both(path),
+            Ok(DirCmpEntry::Left(path, details)) => EitherOrBoth::Left(path, details),
+            Ok(DirCmpEntry::Right(path, details)) => EitherOrBoth::Right(path, details),
+            Ok(DirCmpEntry::TypeMismatch {
+                path,
+                left_kind,
+                right_kind,
+            }) => EitherOrBoth::TypeMismatch {
+                path,
+                left_kind,
+                right_kind,
+            },
+            Ok(DirCmpEntry::Unexplored {
+                path,
+                sides,
+                reason,
+                ..
+            }) => EitherOrBoth::Unexplored {
+                path,
+                sides,
+                reason,
+            },
+            Err(err) => return Some(Err(err)),
+        };
+        Some(Ok(entry))
+    }
+}
 
-fn compare_dirs_inner(
+/// Compares the names of two directories lazily.
+///
+/// This is `full::compare_dirs_iter` with `CompareMode::NAMES` and without metadata or
+/// attribute checks. Sub directories are only descended into if `options.recursive` is set,
+/// otherwise pairs of directories are reported as `Both`. Entries are yielded depth-first
+/// as soon as they are known. Dropping the iterator stops the comparison.
+pub fn compare_dirs_iter(
     left_path: &Path,
     right_path: &Path,
-    left_base: &str,
-    right_base: &str,
-    options: &Options,
-) -> io::Result<Vec<EitherOrBoth>> {
-    trace!("comparing 2 dirs");
-
-    let mut results: Vec<EitherOrBoth> = Vec::new();
-    for dir_entry in zip_dir_entries(
-        &left_path.to_path_buf(),
-        &right_path.to_path_buf(),
-        left_base,
-        right_base,
-        &options.filter,
-    )? {
-        match dir_entry {
-            EitherOrBoth::Both(left_entry, right_entry) => {
-                trace!("handling EitherOrBoth::Both");
-                debug!("comparing{:?} vs {:?}", left_entry, right_entry);
-                if !options.ignore_equal {
-                    if left_entry.is_dir() && right_entry.is_dir() {
-                        //handle two dirs
-                        let subtree_results = compare_dirs_inner(
-                            left_entry.as_path(),
-                            right_entry.as_path(),
-                            left_base,
-                            right_base,
-                            options,
-                        )?;
-                        results.extend(subtree_results);
-                    }
-
-                    //handle two files
-                    if left_entry.is_file() && right_entry.is_file() {
-                        results.push(EitherOrBoth::Both(
-                            left_entry.to_owned(),
-                            right_entry.to_owned(),
-                        ));
-                    }
-
-                    //ignore symlinks and mismatches
-                }
-            }
-            EitherOrBoth::Left(left_entry) => {
-                trace!("handling EitherOrBoth::Left");
-                if !options.ignore_left_only {
-                    if left_entry.is_dir() {
-                        let entry_list = list_files(&left_entry);
-                        for file_path in entry_list {
-                            results.push(EitherOrBoth::Left(file_path));
-                        }
-                        continue;
-                    }
-                    if left_entry.is_file() {
-                        results.push(EitherOrBoth::Left(left_entry));
-                        continue;
-                    }
-                    if left_entry.is_symlink() {
-                        //ignore
-                        continue;
-                    }
-                }
-            }
-            EitherOrBoth::Right(right_entry) => {
-                trace!("handling EitherOrBoth::Right");
-                if !options.ignore_right_only {
-                    if right_entry.is_dir() {
-                        let entry_list = list_files(&right_entry);
-                        for file_path in entry_list {
-                            results.push(EitherOrBoth::Right(file_path));
-                        }
-                        continue;
-                    }
-                    if right_entry.is_file() {
-                        results.push(EitherOrBoth::Right(right_entry));
-                        continue;
-                    }
-                    if right_entry.is_symlink() {
-                        //ignore
-                        continue;
-                    }
-                }
-            }
-        }
-    }
-    Ok(results)
+    options: Options,
+) -> Result<CompareDirsIter, Error> {
+    let options = Options {
+        compare_mode: CompareMode::NAMES,
+        metadata_checks: MetadataChecks::default(),
+        xattrs: None,
+        hard_links: false,
+        ..options
+    };
+    let inner = full::compare_dirs_iter(left_path, right_path, options)?;
+
+    Ok(CompareDirsIter { inner })
+}
+
+pub fn compare_dirs(
+    left_path: &Path,
+    right_path: &Path,
+    options: Options,
+) -> Result<Vec<EitherOrBoth>, Error> {
+    compare_dirs_iter(left_path, right_path, options)?.collect()
 }
 
 #[cfg(test)]
-mod tests_compare_dirs_inner {
+mod tests_compare_dirs {
     use super::*;
+    use crate::{EntryDetails, EntryKind, Roots, SymlinkKind, SymlinkMode};
     use std::fs;
+    use std::sync::Arc;
 
     fn init_logger() {
         let _ = env_logger::builder().is_test(true).try_init();
     }
 
+    fn roots_of(left: &Path, right: &Path) -> Arc<Roots> {
+        Arc::new(Roots {
+            left: left.to_path_buf(),
+            right: right.to_path_buf(),
+        })
+    }
+
+    //details of a regular file existing on one side only
+    fn file() -> EntryDetails {
+        EntryDetails {
+            kind: EntryKind::File,
+            device: None,
+            symlink: None,
+        }
+    }
+
     #[test]
     fn no_restictions() {
         init_logger();
@@ -128,20 +123,17 @@ mod tests_compare_dirs_inner {
             ignore_right_only: false,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<EitherOrBoth> = vec![
-            EitherOrBoth::Left(file_left_only.as_path().to_path_buf()),
-            EitherOrBoth::Both(
-                file_left_both.as_path().to_path_buf(),
-                file_right_both.as_path().to_path_buf(),
-            ),
-            EitherOrBoth::Right(file_right_only.as_path().to_path_buf()),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![
+            EitherOrBoth::Both(roots.left_entry(&file_left_both)),
+            EitherOrBoth::Left(roots.left_entry(&file_left_only), file()),
+            EitherOrBoth::Right(roots.right_entry(&file_right_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -169,16 +161,16 @@ mod tests_compare_dirs_inner {
             ignore_right_only: false,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<EitherOrBoth> = vec![
-            EitherOrBoth::Left(file_left_only.as_path().to_path_buf()),
-            EitherOrBoth::Right(file_right_only.as_path().to_path_buf()),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![
+            EitherOrBoth::Left(roots.left_entry(&file_left_only), file()),
+            EitherOrBoth::Right(roots.right_entry(&file_right_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -206,19 +198,16 @@ mod tests_compare_dirs_inner {
             ignore_right_only: false,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<EitherOrBoth> = vec![
-            EitherOrBoth::Both(
-                file_left_both.as_path().to_path_buf(),
-                file_right_both.as_path().to_path_buf(),
-            ),
-            EitherOrBoth::Right(file_right_only.as_path().to_path_buf()),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![
+            EitherOrBoth::Both(roots.left_entry(&file_left_both)),
+            EitherOrBoth::Right(roots.right_entry(&file_right_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -246,55 +235,216 @@ mod tests_compare_dirs_inner {
             ignore_right_only: true,
             filter: None,
             recursive: false,
+            ..Default::default()
         };
 
-        let mut expected: Vec<EitherOrBoth> = vec![
-            EitherOrBoth::Left(file_left_only.as_path().to_path_buf()),
-            EitherOrBoth::Both(
-                file_left_both.as_path().to_path_buf(),
-                file_right_both.as_path().to_path_buf(),
-            ),
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![
+            EitherOrBoth::Both(roots.left_entry(&file_left_both)),
+            EitherOrBoth::Left(roots.left_entry(&file_left_only), file()),
         ];
-        expected.sort();
         //compare
-        let mut result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
-        result.sort();
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
         assert_eq!(result, expected);
     }
-}
 
-pub fn compare_dirs(
-    left_path: &Path,
-    right_path: &Path,
-    options: Options,
-) -> io::Result<Vec<EitherOrBoth>> {
-    debug!(
-        "starting to compare for {:?} vs {:?}",
-        left_path, right_path
-    );
-
-    if !left_path.exists() {
-        error!("The left path does not exists!");
-        panic!();
+    #[test]
+    fn type_mismatch() {
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_mismatch = left_dir.path().join("mismatch");
+        fs::create_dir(left_mismatch.as_path()).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_mismatch = right_dir.path().join("mismatch");
+        fs::write(right_mismatch.as_path(), b"file on the right").unwrap();
+
+        let diff_options = Options {
+            ignore_equal: false,
+            ignore_left_only: false,
+            ignore_right_only: false,
+            filter: None,
+            recursive: false,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![EitherOrBoth::TypeMismatch {
+            path: roots.left_entry(&left_mismatch),
+            left_kind: EntryKind::Dir,
+            right_kind: EntryKind::File,
+        }];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn recursive_false() {
+        init_logger();
+        //prepare both dirs, the sub directories only differ in their contents
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub_dir = left_dir.path().join("sub");
+        fs::create_dir(left_sub_dir.as_path()).unwrap();
+        fs::write(left_sub_dir.join("left_only.txt"), b"Lefty left").unwrap();
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(right_dir.path().join("sub")).unwrap();
+
+        let diff_options = Options {
+            recursive: false,
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![EitherOrBoth::Both(roots.left_entry(&left_sub_dir))];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
     }
 
-    if !left_path.is_dir() {
-        error!("The left path is not a directory!");
-        panic!();
+    #[test]
+    fn include_nested() {
+        use crate::FilterSet;
+        use regex::bytes::Regex;
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub_dir = left_dir.path().join("src/bin");
+        fs::create_dir_all(left_sub_dir.as_path()).unwrap();
+        let file_left_both = left_sub_dir.join("tool.rs");
+        fs::write(file_left_both.as_path(), b"Left and Right").unwrap();
+        fs::write(left_sub_dir.join("notes.txt"), b"Lefty left").unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_sub_dir = right_dir.path().join("src/bin");
+        fs::create_dir_all(right_sub_dir.as_path()).unwrap();
+        fs::write(right_sub_dir.join("tool.rs"), b"Right and Left").unwrap();
+        let file_right_only = right_dir.path().join("src/lib.rs");
+        fs::write(file_right_only.as_path(), b"Righty right").unwrap();
+
+        let diff_options = Options {
+            recursive: true,
+            filter: Some(FilterSet {
+                include: vec![Regex::new(r"\.rs$").unwrap()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![
+            EitherOrBoth::Both(roots.left_entry(&file_left_both)),
+            EitherOrBoth::Right(roots.right_entry(&file_right_only), file()),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
     }
 
-    if !right_path.exists() {
-        error!("The right path does not exists!");
-        panic!();
+    #[test]
+    fn metadata_filter() {
+        use crate::{FilterSet, MetadataFilter};
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let file_left_both = left_dir.path().join("small.txt");
+        fs::write(file_left_both.as_path(), b"Left").unwrap();
+        fs::write(left_dir.path().join("large.bin"), [0u8; 100]).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::write(right_dir.path().join("small.txt"), b"Right").unwrap();
+
+        let diff_options = Options {
+            filter: Some(FilterSet {
+                metadata: Some(MetadataFilter {
+                    max_size: Some(10),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> =
+            vec![EitherOrBoth::Both(roots.left_entry(&file_left_both))];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
     }
 
-    if !right_path.is_dir() {
-        error!("The right path is not a directory!");
-        panic!();
+    #[test]
+    fn max_depth() {
+        use crate::{Sides, Unexplored};
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_sub_dir = left_dir.path().join("sub");
+        fs::create_dir_all(left_sub_dir.join("deep")).unwrap();
+        fs::write(left_sub_dir.join("deep/file.txt"), b"Left").unwrap();
+        let file_left_both = left_dir.path().join("file.txt");
+        fs::write(file_left_both.as_path(), b"Left").unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        fs::create_dir(right_dir.path().join("sub")).unwrap();
+        fs::write(right_dir.path().join("file.txt"), b"Right").unwrap();
+
+        let diff_options = Options {
+            recursive: true,
+            max_depth: Some(1),
+            ..Default::default()
+        };
+
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![
+            EitherOrBoth::Both(roots.left_entry(&file_left_both)),
+            EitherOrBoth::Unexplored {
+                path: roots.left_entry(&left_sub_dir),
+                sides: Sides::Both,
+                reason: Unexplored::DepthLimit,
+            },
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
     }
 
-    let left_base = left_path.to_str().unwrap();
-    let right_base = right_path.to_str().unwrap();
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_target() {
+        use std::os::unix::fs::symlink;
+        init_logger();
+        //prepare left dir
+        let left_dir = tempfile::Builder::new().tempdir().unwrap();
+        let left_link = left_dir.path().join("link");
+        symlink("missing", left_link.as_path()).unwrap();
+        let left_only_link = left_dir.path().join("left_only");
+        symlink("missing", left_only_link.as_path()).unwrap();
+
+        //prepare right dir
+        let right_dir = tempfile::Builder::new().tempdir().unwrap();
+        let right_link = right_dir.path().join("link");
+        symlink("other", right_link.as_path()).unwrap();
 
-    compare_dirs_inner(left_path, right_path, left_base, right_base, &options)
+        let diff_options = Options {
+            symlinks: SymlinkMode::Target,
+            ..Default::default()
+        };
+
+        //light only compares names, so the different targets are not reported
+        let roots = roots_of(left_dir.path(), right_dir.path());
+        let expected: Vec<EitherOrBoth> = vec![
+            EitherOrBoth::Left(
+                roots.left_entry(&left_only_link),
+                EntryDetails {
+                    kind: EntryKind::Symlink,
+                    device: None,
+                    symlink: Some(SymlinkKind::Dangling("missing".into())),
+                },
+            ),
+            EitherOrBoth::Both(roots.left_entry(&left_link)),
+        ];
+        let result = compare_dirs(left_dir.path(), right_dir.path(), diff_options).unwrap();
+        assert_eq!(result, expected);
+    }
 }
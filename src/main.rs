@@ -1,9 +1,17 @@
 use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use dir_cmp::{full::compare_dirs, Options};
+use dir_cmp::{
+    full::compare_dirs_iter, CompareMode, DigestCache, EntryKind, Error, FilterSet, GlobFilter,
+    HashAlgorithm, IgnoreRules, MetadataChecks, MetadataFilter, NameMatching, Options, SymlinkMode,
+    XattrChecks,
+};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use log::debug;
+use regex::bytes::Regex;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -17,27 +25,366 @@ struct Cli {
     #[arg(short, long)]
     recursive: bool,
 
+    /// descend at most this many levels, deeper directories are reported as unexplored
+    #[arg(long, value_name = "N", requires = "recursive")]
+    max_depth: Option<usize>,
+
+    /// do not report entries above this depth
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    min_depth: usize,
+
+    /// do not descend into directories on other file systems than the compared roots
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+
+    /// match names which only differ in case, like on case-insensitive file systems
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+
+    /// match names which only differ in their Unicode normalization, like NFC and NFD
+    #[arg(long)]
+    normalize_unicode: bool,
+
     /// show identical files
     #[arg(short)]
     show_same: bool,
+
+    /// number of threads comparing file contents
+    #[arg(short = 'j', long, default_value_t = 1)]
+    threads: usize,
+
+    /// how files with the same name are compared
+    #[arg(short, long, value_enum, default_value_t = Mode::Content)]
+    mode: Mode,
+
+    /// hash algorithm used by the checksum mode
+    #[arg(long, value_enum, default_value_t = Hash::Sha256)]
+    hash: Hash,
+
+    /// file caching digests between runs of the checksum mode
+    #[arg(long, value_name = "FILE")]
+    digest_cache: Option<PathBuf>,
+
+    /// drop digests of files not hashed by this run from the digest cache, only use it if
+    /// the run compares the whole trees the cache is kept for
+    #[arg(long, requires = "digest_cache")]
+    prune_digests: bool,
+
+    /// how symlinks are handled
+    #[arg(long, value_enum, default_value_t = Symlinks::Ignore)]
+    symlinks: Symlinks,
+
+    /// metadata attributes compared in addition to the mode, comma separated
+    #[arg(long, value_enum, value_delimiter = ',')]
+    metadata: Vec<Attribute>,
+
+    /// seconds the modification times may differ by the mtime metadata check
+    #[arg(long, value_name = "SECONDS", default_value = "0", value_parser = parse_seconds)]
+    mtime_tolerance: Duration,
+
+    /// compare extended attributes and ACLs
+    #[arg(long)]
+    xattrs: bool,
+
+    /// only compare extended attributes of these namespaces, e.g. security or user
+    #[arg(long, value_name = "NAMESPACE", requires = "xattrs")]
+    xattr_include: Vec<String>,
+
+    /// never compare extended attributes of these namespaces
+    #[arg(long, value_name = "NAMESPACE", requires = "xattrs")]
+    xattr_exclude: Vec<String>,
+
+    /// report files which are hard linked differently on both sides
+    #[arg(long)]
+    hard_links: bool,
+
+    /// only compare files matching any of these regular expressions
+    #[arg(long, value_name = "REGEX")]
+    include: Vec<Regex>,
+
+    /// skip entries matching any of these regular expressions, wins over --include
+    #[arg(long, value_name = "REGEX")]
+    exclude: Vec<Regex>,
+
+    /// skip entries matching this gitignore style pattern, `!` re-includes them
+    #[arg(long, value_name = "PATTERN")]
+    glob: Vec<String>,
+
+    /// like --glob, but only applied to the left tree
+    #[arg(long, value_name = "PATTERN")]
+    left_glob: Vec<String>,
+
+    /// like --glob, but only applied to the right tree
+    #[arg(long, value_name = "PATTERN")]
+    right_glob: Vec<String>,
+
+    /// skip entries ignored by .gitignore, .ignore and .dircmpignore files, using the files
+    /// of the given side
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SIDE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "either"
+    )]
+    ignore_files: Option<IgnoreSide>,
+
+    /// skip files smaller than this many bytes
+    #[arg(long, value_name = "BYTES")]
+    min_size: Option<u64>,
+
+    /// skip files larger than this many bytes
+    #[arg(long, value_name = "BYTES")]
+    max_size: Option<u64>,
+
+    /// skip files modified less than this many seconds ago
+    #[arg(long, value_name = "SECONDS")]
+    min_age: Option<u64>,
+
+    /// skip files modified more than this many seconds ago
+    #[arg(long, value_name = "SECONDS")]
+    max_age: Option<u64>,
+
+    /// only compare entries of these kinds, comma separated, directories are always walked
+    #[arg(long, value_enum, value_delimiter = ',')]
+    kind: Vec<Kind>,
+
+    /// only compare files whose executable bit is set, or not set
+    #[arg(long, value_name = "BOOL")]
+    executable: Option<bool>,
 }
 
-fn main() {
-    env_logger::init();
+#[derive(Clone, Copy, ValueEnum)]
+enum Mode {
+    /// names only
+    Names,
+    /// file sizes
+    Size,
+    /// file sizes and modification times
+    Quick,
+    /// file sizes and contents
+    Content,
+    /// file sizes and digests
+    Checksum,
+}
 
-    let cli = Cli::parse();
+impl From<Mode> for CompareMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Names => CompareMode::NAMES,
+            Mode::Size => CompareMode::SIZE_ONLY,
+            Mode::Quick => CompareMode::QUICK,
+            Mode::Content => CompareMode::CONTENT,
+            Mode::Checksum => CompareMode::CHECKSUM,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Hash {
+    Sha256,
+    Blake3,
+    Xxh3,
+}
+
+impl From<Hash> for HashAlgorithm {
+    fn from(hash: Hash) -> Self {
+        match hash {
+            Hash::Sha256 => HashAlgorithm::Sha256,
+            Hash::Blake3 => HashAlgorithm::Blake3,
+            Hash::Xxh3 => HashAlgorithm::Xxh3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Symlinks {
+    /// skip symlinks
+    Ignore,
+    /// compare symlink targets
+    Target,
+    /// follow symlinks
+    Follow,
+}
+
+impl From<Symlinks> for SymlinkMode {
+    fn from(symlinks: Symlinks) -> Self {
+        match symlinks {
+            Symlinks::Ignore => SymlinkMode::Ignore,
+            Symlinks::Target => SymlinkMode::Target,
+            Symlinks::Follow => SymlinkMode::Follow,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum IgnoreSide {
+    /// entries ignored on either side
+    Either,
+    /// entries ignored on both sides
+    Both,
+    /// entries ignored on the left side
+    Left,
+    /// entries ignored on the right side
+    Right,
+}
+
+impl From<IgnoreSide> for IgnoreRules {
+    fn from(side: IgnoreSide) -> Self {
+        match side {
+            IgnoreSide::Either => IgnoreRules::Either,
+            IgnoreSide::Both => IgnoreRules::Both,
+            IgnoreSide::Left => IgnoreRules::Left,
+            IgnoreSide::Right => IgnoreRules::Right,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Attribute {
+    /// permission bits
+    Mode,
+    /// owning user and group
+    Owner,
+    /// modification time
+    Mtime,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Kind {
+    File,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl From<Kind> for EntryKind {
+    fn from(kind: Kind) -> Self {
+        match kind {
+            Kind::File => EntryKind::File,
+            Kind::Symlink => EntryKind::Symlink,
+            Kind::Fifo => EntryKind::Fifo,
+            Kind::Socket => EntryKind::Socket,
+            Kind::BlockDevice => EntryKind::BlockDevice,
+            Kind::CharDevice => EntryKind::CharDevice,
+        }
+    }
+}
+
+//parses a non-negative, finite number of seconds
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value.parse().map_err(|err| format!("{}", err))?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("{} is not a non-negative, finite number of seconds", value))
+}
+
+fn metadata_filter(cli: &Cli) -> Option<MetadataFilter> {
+    let now = SystemTime::now();
+    let before = |age: u64| now.checked_sub(Duration::from_secs(age));
+    let filter = MetadataFilter {
+        min_size: cli.min_size,
+        max_size: cli.max_size,
+        modified_after: cli.max_age.and_then(before),
+        modified_before: cli.min_age.and_then(before),
+        kinds: cli.kind.iter().map(|&kind| kind.into()).collect(),
+        executable: cli.executable,
+    };
+    (filter != MetadataFilter::default()).then_some(filter)
+}
+
+fn glob_filter(patterns: &[String]) -> Result<Option<GlobFilter>, Error> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    GlobFilter::new(patterns).map(Some)
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    let digest_cache = match &cli.digest_cache {
+        Some(path) => Some(Arc::new(DigestCache::open(path)?)),
+        None => None,
+    };
+
+    let filter = FilterSet {
+        include: cli.include.clone(),
+        exclude: cli.exclude.clone(),
+        glob: glob_filter(&cli.glob)?,
+        metadata: metadata_filter(&cli),
+    };
+    let left_filter = glob_filter(&cli.left_glob)?.map(|glob| FilterSet {
+        glob: Some(glob),
+        ..Default::default()
+    });
+    let right_filter = glob_filter(&cli.right_glob)?.map(|glob| FilterSet {
+        glob: Some(glob),
+        ..Default::default()
+    });
 
     //create options without any restrictions
     let diff_options = Options {
         ignore_equal: !cli.show_same,
         ignore_left_only: false,
         ignore_right_only: false,
-        filter: None,
+        filter: Some(filter),
+        left_filter,
+        right_filter,
         recursive: cli.recursive,
+        threads: cli.threads,
+        compare_mode: cli.mode.into(),
+        hash_algorithm: cli.hash.into(),
+        digest_cache: digest_cache.clone(),
+        symlinks: cli.symlinks.into(),
+        metadata_checks: MetadataChecks {
+            mode: cli.metadata.contains(&Attribute::Mode),
+            owner: cli.metadata.contains(&Attribute::Owner),
+            mtime: cli.metadata.contains(&Attribute::Mtime),
+            mtime_tolerance: cli.mtime_tolerance,
+        },
+        xattrs: cli.xattrs.then(|| XattrChecks {
+            include: cli.xattr_include.clone(),
+            exclude: cli.xattr_exclude.clone(),
+        }),
+        hard_links: cli.hard_links,
+        ignore_files: cli.ignore_files.map(IgnoreRules::from),
+        max_depth: cli.max_depth,
+        min_depth: cli.min_depth,
+        one_file_system: cli.one_file_system,
+        name_matching: NameMatching {
+            case_insensitive: cli.ignore_case,
+            unicode_normalization: cli.normalize_unicode,
+        },
     };
 
     debug!("used options: {:?}", diff_options);
+    //without hashing no digest is used, pruning would empty the cache
+    let prune_digests = cli.prune_digests && diff_options.compare_mode.hash;
+
+    //entries are printed with `Debug`, which quotes paths and escapes bytes that are not
+    //valid UTF-8 as `\xNN`, so every name is printed unambiguously on a single line
+    for entry in compare_dirs_iter(&cli.left, &cli.right, diff_options)? {
+        println!("{:?}", entry?);
+    }
+
+    //only reached if the comparison finished without errors
+    if let Some(digest_cache) = digest_cache {
+        if prune_digests {
+            digest_cache.prune_unused();
+        }
+        digest_cache.save()?;
+    }
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
+    let cli = Cli::parse();
 
-    let result = compare_dirs(&cli.left, &cli.right, diff_options).unwrap();
-    println!("{:?}", result)
+    if let Err(err) = run(cli) {
+        eprintln!("dir-cmp: {}", err);
+        process::exit(2);
+    }
 }
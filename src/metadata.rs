@@ -0,0 +1,195 @@
+use std::fs::Metadata;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::compare::read_error;
+use crate::Error;
+
+/// Metadata attributes compared in addition to the checks of the `CompareMode`.
+///
+/// Metadata differences do not change the `FileCompResult` of two files, they are listed
+/// in `FileCompDetails::metadata` instead.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataChecks {
+    /// compare the permission bits, including setuid, setgid and sticky bit, unix only
+    pub mode: bool,
+    /// compare the owning user and group, unix only
+    pub owner: bool,
+    /// compare the modification time
+    pub mtime: bool,
+    /// largest difference of the modification times which is still considered equal
+    pub mtime_tolerance: Duration,
+}
+
+impl MetadataChecks {
+    /// compare permissions, ownership and exact modification times
+    pub const ALL: MetadataChecks = MetadataChecks {
+        mode: true,
+        owner: true,
+        mtime: true,
+        mtime_tolerance: Duration::ZERO,
+    };
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.mode || self.owner || self.mtime
+    }
+}
+
+/// A metadata attribute which differs between two files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MetadataDiff {
+    /// permission bits
+    Mode { left: u32, right: u32 },
+    /// owning user id
+    Uid { left: u32, right: u32 },
+    /// owning group id
+    Gid { left: u32, right: u32 },
+    /// modification time, differing by more than the tolerance
+    Mtime { left: SystemTime, right: SystemTime },
+}
+
+//returns the differing attributes in the order they are declared in `MetadataDiff`
+pub(crate) fn metadata_diffs(
+    checks: &MetadataChecks,
+    left_path: &Path,
+    left_metadata: &Metadata,
+    right_path: &Path,
+    right_metadata: &Metadata,
+) -> Result<Vec<MetadataDiff>, Error> {
+    let mut diffs = Vec::new();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        //the upper bits of the mode hold the file type
+        let (left_mode, right_mode) = (
+            left_metadata.mode() & 0o7777,
+            right_metadata.mode() & 0o7777,
+        );
+        if checks.mode && left_mode != right_mode {
+            diffs.push(MetadataDiff::Mode {
+                left: left_mode,
+                right: right_mode,
+            });
+        }
+        if checks.owner && left_metadata.uid() != right_metadata.uid() {
+            diffs.push(MetadataDiff::Uid {
+                left: left_metadata.uid(),
+                right: right_metadata.uid(),
+            });
+        }
+        if checks.owner && left_metadata.gid() != right_metadata.gid() {
+            diffs.push(MetadataDiff::Gid {
+                left: left_metadata.gid(),
+                right: right_metadata.gid(),
+            });
+        }
+    }
+
+    if checks.mtime {
+        let left_mtime = left_metadata.modified().map_err(read_error(left_path))?;
+        let right_mtime = right_metadata.modified().map_err(read_error(right_path))?;
+        let difference = match left_mtime.duration_since(right_mtime) {
+            Ok(difference) => difference,
+            Err(err) => err.duration(),
+        };
+        if difference > checks.mtime_tolerance {
+            diffs.push(MetadataDiff::Mtime {
+                left: left_mtime,
+                right: right_mtime,
+            });
+        }
+    }
+
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests_metadata_diffs {
+    use super::*;
+    use std::fs::{self, File};
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("metadata_diffs_")
+            .tempdir()
+            .unwrap()
+    }
+
+    fn diffs_of(checks: &MetadataChecks, left_path: &Path, right_path: &Path) -> Vec<MetadataDiff> {
+        let left_metadata = fs::metadata(left_path).unwrap();
+        let right_metadata = fs::metadata(right_path).unwrap();
+        metadata_diffs(
+            checks,
+            left_path,
+            &left_metadata,
+            right_path,
+            &right_metadata,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn mtime_tolerance() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, world!").unwrap();
+        let now = SystemTime::now();
+        let set_modified = |path: &Path, mtime| {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap()
+        };
+        set_modified(&left_file, now);
+        set_modified(&right_file, now - Duration::from_secs(1));
+
+        let mut checks = MetadataChecks {
+            mtime: true,
+            ..Default::default()
+        };
+        let diffs = diffs_of(&checks, &left_file, &right_file);
+        assert!(matches!(diffs.as_slice(), [MetadataDiff::Mtime { .. }]));
+
+        checks.mtime_tolerance = Duration::from_secs(2);
+        assert_eq!(diffs_of(&checks, &left_file, &right_file), Vec::new());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        fs::set_permissions(&left_file, fs::Permissions::from_mode(0o644)).unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, world!").unwrap();
+        fs::set_permissions(&right_file, fs::Permissions::from_mode(0o777)).unwrap();
+
+        //the owner is the same for both files
+        let diffs = diffs_of(&MetadataChecks::ALL, &left_file, &right_file);
+        assert_eq!(
+            diffs.first(),
+            Some(&MetadataDiff::Mode {
+                left: 0o644,
+                right: 0o777
+            })
+        );
+        assert!(!diffs
+            .iter()
+            .any(|diff| matches!(diff, MetadataDiff::Uid { .. } | MetadataDiff::Gid { .. })));
+
+        let checks = MetadataChecks {
+            owner: true,
+            ..Default::default()
+        };
+        assert_eq!(diffs_of(&checks, &left_file, &right_file), Vec::new());
+    }
+}
@@ -0,0 +1,150 @@
+use caseless::Caseless;
+use std::ffi::{OsStr, OsString};
+use unicode_normalization::{is_nfc, is_nfd, UnicodeNormalization};
+
+/// How the names of entries are matched between both trees.
+///
+/// By default names must be equal byte by byte. Names which are not valid UTF-8 are always
+/// matched that way.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameMatching {
+    /// match names which only differ in case, using Unicode case folding, e.g. for files
+    /// copied from a case-insensitive file system
+    pub case_insensitive: bool,
+    /// match names which only differ in their Unicode normalization, e.g. NFD names written
+    /// by macOS and NFC names written on Linux
+    pub unicode_normalization: bool,
+}
+
+/// How the names of an entry matched by [`NameMatching`] differ between both sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NameDifference {
+    /// the names only differ in case
+    Case,
+    /// the names are equal after Unicode normalization, e.g. NFC and NFD
+    Normalization,
+    /// the names differ in case and are written in different normalization forms
+    CaseAndNormalization,
+}
+
+impl NameDifference {
+    //classifies two different spellings of the same entry
+    pub(crate) fn between(left: &OsStr, right: &OsStr) -> Self {
+        let (left, right) = match (left.to_str(), right.to_str()) {
+            (Some(left), Some(right)) => (left, right),
+            //only valid UTF-8 names can be matched by anything but their case
+            _ => return NameDifference::Case,
+        };
+        if left.nfc().eq(right.nfc()) {
+            return NameDifference::Normalization;
+        }
+        if is_nfc(left) == is_nfc(right) && is_nfd(left) == is_nfd(right) {
+            NameDifference::Case
+        } else {
+            NameDifference::CaseAndNormalization
+        }
+    }
+}
+
+impl NameMatching {
+    //names with the same key are matched
+    pub(crate) fn key(&self, name: &OsStr) -> OsString {
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => return name.to_os_string(),
+        };
+        match (self.case_insensitive, self.unicode_normalization) {
+            //canonical caseless matching, folding can produce unnormalized text
+            (true, true) => name
+                .nfd()
+                .default_case_fold()
+                .nfd()
+                .collect::<String>()
+                .into(),
+            (true, false) => caseless::default_case_fold_str(name).into(),
+            (false, true) => name.nfd().collect::<String>().into(),
+            (false, false) => name.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_name_matching {
+    use super::*;
+
+    fn matches(matching: &NameMatching, left: &str, right: &str) -> bool {
+        matching.key(OsStr::new(left)) == matching.key(OsStr::new(right))
+    }
+
+    #[test]
+    fn exact() {
+        let matching = NameMatching::default();
+        assert!(matches(&matching, "README.md", "README.md"));
+        assert!(!matches(&matching, "README.md", "Readme.md"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let matching = NameMatching {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        assert!(matches(&matching, "README.md", "Readme.md"));
+        assert!(matches(&matching, "ÄRGER.txt", "ärger.txt"));
+        //full case folding maps the sharp s to "ss"
+        assert!(matches(&matching, "STRASSE", "straße"));
+        assert!(!matches(&matching, "README.md", "README.txt"));
+    }
+
+    #[test]
+    fn unicode_normalization() {
+        let nfc = "caf\u{e9}.txt";
+        let nfd = "cafe\u{301}.txt";
+        let matching = NameMatching {
+            unicode_normalization: true,
+            ..Default::default()
+        };
+        assert!(matches(&matching, nfc, nfd));
+        assert!(!matches(&matching, nfc, "CAF\u{c9}.txt"));
+        assert!(!matches(&NameMatching::default(), nfc, nfd));
+
+        let matching = NameMatching {
+            case_insensitive: true,
+            unicode_normalization: true,
+        };
+        assert!(matches(&matching, nfd, "CAF\u{c9}.txt"));
+    }
+
+    #[test]
+    fn difference() {
+        let between = |left, right| NameDifference::between(OsStr::new(left), OsStr::new(right));
+        assert_eq!(
+            between("caf\u{e9}.txt", "cafe\u{301}.txt"),
+            NameDifference::Normalization
+        );
+        assert_eq!(between("README.md", "Readme.md"), NameDifference::Case);
+        assert_eq!(
+            between("caf\u{e9}.txt", "CAF\u{c9}.txt"),
+            NameDifference::Case
+        );
+        assert_eq!(
+            between("caf\u{e9}.txt", "CAFE\u{301}.txt"),
+            NameDifference::CaseAndNormalization
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let matching = NameMatching {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let upper = OsStr::from_bytes(b"CAF\xc9.txt");
+        let lower = OsStr::from_bytes(b"caf\xe9.txt");
+        assert_eq!(matching.key(upper), upper);
+        assert_ne!(matching.key(upper), matching.key(lower));
+    }
+}
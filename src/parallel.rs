@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::compare::FileComparer;
+use crate::{Error, FileCompDetails, FileCompResult};
+
+type Job = (usize, PathBuf, PathBuf);
+type JobResult = (usize, Result<(FileCompResult, FileCompDetails), Error>);
+
+/// Fixed set of worker threads comparing file pairs.
+///
+/// Jobs are identified by the id returned from [`ComparePool::submit`], so the caller can
+/// collect the results in submission order no matter which worker finishes first.
+pub(crate) struct ComparePool {
+    threads: usize,
+    jobs: Option<Sender<Job>>,
+    results: Receiver<JobResult>,
+    finished: HashMap<usize, Result<(FileCompResult, FileCompDetails), Error>>,
+    next_id: usize,
+    workers: Vec<JoinHandle<()>>,
+    //shared with the comparer of the workers
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ComparePool {
+    pub(crate) fn new(threads: usize, comparer: FileComparer) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, result_receiver) = mpsc::channel::<JobResult>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let cancelled = Arc::clone(comparer.cancelled());
+        let comparer = Arc::new(comparer);
+
+        let workers = (0..threads)
+            .map(|_| {
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                let comparer = Arc::clone(&comparer);
+                let cancelled = Arc::clone(&cancelled);
+                thread::spawn(move || loop {
+                    let job = job_receiver.lock().unwrap().recv();
+                    let (id, left_path, right_path) = match job {
+                        Ok(job) => job,
+                        //pool was dropped
+                        Err(_) => break,
+                    };
+                    //queued jobs are skipped once the pool is dropped
+                    if cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let comp_result = comparer.compare(&left_path, &right_path);
+                    if result_sender.send((id, comp_result)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        ComparePool {
+            threads,
+            jobs: Some(job_sender),
+            results: result_receiver,
+            finished: HashMap::new(),
+            next_id: 0,
+            workers,
+            cancelled,
+        }
+    }
+
+    pub(crate) fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub(crate) fn submit(&mut self, left_path: PathBuf, right_path: PathBuf) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs
+            .as_ref()
+            .unwrap()
+            .send((id, left_path, right_path))
+            .expect("compare workers stopped unexpectedly");
+        id
+    }
+
+    //blocks until the job with the given id is done
+    pub(crate) fn wait(&mut self, id: usize) -> Result<(FileCompResult, FileCompDetails), Error> {
+        loop {
+            if let Some(comp_result) = self.finished.remove(&id) {
+                return comp_result;
+            }
+            let (finished_id, comp_result) = self
+                .results
+                .recv()
+                .expect("compare workers stopped unexpectedly");
+            self.finished.insert(finished_id, comp_result);
+        }
+    }
+}
+
+impl Drop for ComparePool {
+    fn drop(&mut self) {
+        //running comparisons stop at their next block, queued ones are not started at all
+        self.cancelled.store(true, Ordering::Relaxed);
+        //closing the job channel lets the workers run out of their loop
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs;
+use std::time::SystemTime;
+
+use crate::EntryKind;
+
+/// Predicates over the metadata of an entry, all of the given ones must hold for an entry
+/// to be compared.
+///
+/// The predicates are not applied to directories, they are always walked since they could
+/// contain matching entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataFilter {
+    /// smallest size in bytes
+    pub min_size: Option<u64>,
+    /// largest size in bytes
+    pub max_size: Option<u64>,
+    /// earliest modification time
+    pub modified_after: Option<SystemTime>,
+    /// latest modification time
+    pub modified_before: Option<SystemTime>,
+    /// kinds of compared entries, all kinds if empty
+    pub kinds: Vec<EntryKind>,
+    /// only compare entries whose executable bit for the owner is set, or not set
+    pub executable: Option<bool>,
+}
+
+impl MetadataFilter {
+    /// true if the entry of the given kind satisfies all predicates
+    pub fn matches(&self, kind: EntryKind, metadata: &fs::Metadata) -> bool {
+        if kind == EntryKind::Dir {
+            return true;
+        }
+        if !self.kinds.is_empty() && !self.kinds.contains(&kind) {
+            return false;
+        }
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min_size| size < min_size)
+            || self.max_size.is_some_and(|max_size| size > max_size)
+        {
+            return false;
+        }
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            //entries without a modification time can not be in the range
+            let modified = match metadata.modified() {
+                Ok(modified) => modified,
+                Err(_) => return false,
+            };
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self.modified_before.is_some_and(|before| modified > before)
+            {
+                return false;
+            }
+        }
+        match self.executable {
+            Some(executable) => is_executable(metadata) == executable,
+            None => true,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o100 != 0
+}
+
+//there is no executable bit outside of unix
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests_metadata_filter {
+    use super::*;
+    use std::time::Duration;
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("metadata_filter_")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn size_range() {
+        let dir = create_temp_dir();
+        let path = dir.path().join("file");
+        fs::write(&path, b"0123456789").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let filter = |min_size, max_size| MetadataFilter {
+            min_size,
+            max_size,
+            ..Default::default()
+        };
+        assert!(filter(Some(10), Some(10)).matches(EntryKind::File, &metadata));
+        assert!(!filter(Some(11), None).matches(EntryKind::File, &metadata));
+        assert!(!filter(None, Some(9)).matches(EntryKind::File, &metadata));
+        //directories are always walked
+        assert!(filter(Some(11), None).matches(EntryKind::Dir, &metadata));
+    }
+
+    #[test]
+    fn mtime_range() {
+        let dir = create_temp_dir();
+        let path = dir.path().join("file");
+        fs::write(&path, b"content").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        let modified = metadata.modified().unwrap();
+        let hour = Duration::from_secs(3600);
+
+        let filter = |modified_after, modified_before| MetadataFilter {
+            modified_after,
+            modified_before,
+            ..Default::default()
+        };
+        assert!(filter(Some(modified - hour), Some(modified + hour))
+            .matches(EntryKind::File, &metadata));
+        assert!(!filter(Some(modified + hour), None).matches(EntryKind::File, &metadata));
+        assert!(!filter(None, Some(modified - hour)).matches(EntryKind::File, &metadata));
+    }
+
+    #[test]
+    fn kinds() {
+        let dir = create_temp_dir();
+        let metadata = fs::metadata(dir.path()).unwrap();
+        let filter = MetadataFilter {
+            kinds: vec![EntryKind::File],
+            ..Default::default()
+        };
+        assert!(filter.matches(EntryKind::File, &metadata));
+        assert!(!filter.matches(EntryKind::Symlink, &metadata));
+        assert!(!filter.matches(EntryKind::Fifo, &metadata));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = create_temp_dir();
+        let path = dir.path().join("script");
+        fs::write(&path, b"#!/bin/sh").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let filter = |executable| MetadataFilter {
+            executable: Some(executable),
+            ..Default::default()
+        };
+        assert!(filter(true).matches(EntryKind::File, &metadata));
+        assert!(!filter(false).matches(EntryKind::File, &metadata));
+    }
+}
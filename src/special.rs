@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::Path;
+
+use crate::compare::read_error;
+use crate::{Check, EntryKind, Error, FileCompResult, SymlinkKind};
+
+/// Major and minor number of a device node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceNumber {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DeviceNumber {
+    //splits the device number the way glibc's `major` and `minor` do
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn from_raw(rdev: u64) -> Self {
+        DeviceNumber {
+            major: (((rdev >> 32) & 0xffff_f000) | ((rdev >> 8) & 0x0000_0fff)) as u32,
+            minor: (((rdev >> 12) & 0xffff_ff00) | (rdev & 0x0000_00ff)) as u32,
+        }
+    }
+
+    //8 bit major and 24 bit minor number, as used by the BSDs
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+    fn from_raw(rdev: u64) -> Self {
+        DeviceNumber {
+            major: ((rdev >> 24) & 0xff) as u32,
+            minor: (rdev & 0x00ff_ffff) as u32,
+        }
+    }
+}
+
+/// Two special files with the same name, e.g. FIFOs or device nodes.
+///
+/// Special files are never opened, device nodes are compared by their device numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpecialFiles {
+    /// the kind of both files
+    pub kind: EntryKind,
+    /// device numbers of the left and right file, if both are device nodes
+    pub devices: Option<(DeviceNumber, DeviceNumber)>,
+}
+
+/// The type of an entry which exists on one side only.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryDetails {
+    /// the kind of the entry, of its target if a symlink was followed
+    pub kind: EntryKind,
+    /// device number, if the entry is a device node
+    pub device: Option<DeviceNumber>,
+    /// how the entry was reached through a symlink, if it is one
+    pub symlink: Option<SymlinkKind>,
+}
+
+impl EntryDetails {
+    //device nodes are not opened, only their device number is read
+    pub(crate) fn of(
+        kind: EntryKind,
+        symlink: Option<SymlinkKind>,
+        path: &Path,
+    ) -> Result<Self, Error> {
+        let device = match kind {
+            EntryKind::BlockDevice | EntryKind::CharDevice => Some(device_number(path)?),
+            _ => None,
+        };
+        Ok(EntryDetails {
+            kind,
+            device,
+            symlink,
+        })
+    }
+}
+
+//true for the kinds which can not be compared by their contents
+pub(crate) fn is_special(kind: EntryKind) -> bool {
+    matches!(
+        kind,
+        EntryKind::Fifo | EntryKind::Socket | EntryKind::BlockDevice | EntryKind::CharDevice
+    )
+}
+
+//compares two special files of the same kind without opening them
+pub(crate) fn compare_specials(
+    kind: EntryKind,
+    left_path: &Path,
+    right_path: &Path,
+) -> Result<(FileCompResult, SpecialFiles), Error> {
+    let devices = match kind {
+        EntryKind::BlockDevice | EntryKind::CharDevice => {
+            Some((device_number(left_path)?, device_number(right_path)?))
+        }
+        _ => None,
+    };
+    let comp_result = match devices {
+        Some((left_device, right_device)) if left_device != right_device => {
+            FileCompResult::Different(Check::Device)
+        }
+        _ => FileCompResult::Equal,
+    };
+    Ok((comp_result, SpecialFiles { kind, devices }))
+}
+
+#[cfg(unix)]
+fn device_number(path: &Path) -> Result<DeviceNumber, Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path).map_err(read_error(path))?;
+    Ok(DeviceNumber::from_raw(metadata.rdev()))
+}
+
+//device nodes only exist on unix
+#[cfg(not(unix))]
+fn device_number(path: &Path) -> Result<DeviceNumber, Error> {
+    fs::metadata(path).map_err(read_error(path))?;
+    Ok(DeviceNumber { major: 0, minor: 0 })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests_compare_specials {
+    use super::*;
+    use std::process::Command;
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("compare_specials_")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn device_numbers() {
+        //makedev(0x1234, 0x10001) as encoded by glibc
+        let device = DeviceNumber::from_raw(0x1000_1002_3401);
+        assert_eq!(
+            device,
+            DeviceNumber {
+                major: 0x1234,
+                minor: 0x10001
+            }
+        );
+    }
+
+    #[test]
+    fn char_devices() {
+        let dir = create_temp_dir();
+        let left_device = dir.path().join("left");
+        let right_device = dir.path().join("right");
+        //creating device nodes needs privileges
+        let created = Command::new("mknod")
+            .arg(&left_device)
+            .args(["c", "1", "3"])
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if !created {
+            return;
+        }
+        Command::new("mknod")
+            .arg(&right_device)
+            .args(["c", "1", "5"])
+            .status()
+            .unwrap();
+
+        let (comp_result, specials) =
+            compare_specials(EntryKind::CharDevice, &left_device, &right_device).unwrap();
+        assert_eq!(comp_result, FileCompResult::Different(Check::Device));
+        assert_eq!(
+            specials.devices,
+            Some((
+                DeviceNumber { major: 1, minor: 3 },
+                DeviceNumber { major: 1, minor: 5 }
+            ))
+        );
+
+        //a device node on one side only keeps its number
+        let details = EntryDetails::of(EntryKind::CharDevice, None, &left_device).unwrap();
+        assert_eq!(details.device, Some(DeviceNumber { major: 1, minor: 3 }));
+    }
+}
@@ -0,0 +1,615 @@
+use log::{trace, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::vec;
+
+use crate::compare::read_error;
+use crate::ignore::Ignores;
+use crate::special::is_special;
+use crate::{
+    classify_entry, filter_entries, read_dir_paths, zip_dir_entries, EntryKind, Error, FilterSet,
+    Options, SymlinkKind, SymlinkMode, Unexplored, ZipEntry,
+};
+
+/// An entry found by the [`DirWalker`].
+#[derive(Debug)]
+pub(crate) enum WalkEntry {
+    /// two files, either of them may have been reached through a followed symlink
+    Files {
+        left: PathBuf,
+        right: PathBuf,
+        left_symlink: Option<SymlinkKind>,
+        right_symlink: Option<SymlinkKind>,
+    },
+    /// two directories, yielded if they are not descended into or, before their contents,
+    /// if their attributes are compared
+    Dirs {
+        left: PathBuf,
+        right: PathBuf,
+        left_symlink: Option<SymlinkKind>,
+        right_symlink: Option<SymlinkKind>,
+        descended: bool,
+    },
+    /// two symlinks which are compared by their targets
+    Symlinks {
+        left: PathBuf,
+        right: PathBuf,
+        left_symlink: SymlinkKind,
+        right_symlink: SymlinkKind,
+    },
+    /// two special files of the same kind, which must not be opened
+    Specials {
+        left: PathBuf,
+        right: PathBuf,
+        kind: EntryKind,
+    },
+    /// an entry on the left only, directories are only yielded if not descended into
+    Left {
+        entry: PathBuf,
+        kind: EntryKind,
+        symlink: Option<SymlinkKind>,
+    },
+    /// an entry on the right only, directories are only yielded if not descended into
+    Right {
+        entry: PathBuf,
+        kind: EntryKind,
+        symlink: Option<SymlinkKind>,
+    },
+    /// entries of different kinds
+    TypeMismatch {
+        left: PathBuf,
+        right: PathBuf,
+        left_kind: EntryKind,
+        right_kind: EntryKind,
+    },
+    /// a directory which is not descended into, on the sides it is not descended into on
+    Unexplored {
+        entry: ZipEntry,
+        reason: Unexplored,
+        left_symlink: Option<SymlinkKind>,
+        right_symlink: Option<SymlinkKind>,
+    },
+}
+
+enum FrameEntries {
+    Both(vec::IntoIter<ZipEntry>),
+    Left(vec::IntoIter<PathBuf>),
+    Right(vec::IntoIter<PathBuf>),
+}
+
+//one level of the depth-first traversal
+struct Frame {
+    entries: FrameEntries,
+    //canonical paths of the walked directories, only known when following symlinks
+    left_dir: Option<PathBuf>,
+    right_dir: Option<PathBuf>,
+    //ignore files applying to the walked directories
+    ignores: Ignores,
+    //depth of the entries, the entries of the roots are at depth 1
+    depth: usize,
+}
+
+/// Lazy depth-first walk over two directory trees.
+///
+/// Yields pairs of files, one sided files and, if not recursive, pairs of directories.
+/// Directories at `Options::max_depth` are yielded as unexplored instead of being descended
+/// into, entries above `Options::min_depth` are walked but not yielded. With
+/// `Options::one_file_system`, directories on another device than their root are yielded as
+/// unexplored mount points.
+/// Sub directories are only read once the walk reaches them, so dropping the walker stops
+/// all further work.
+pub(crate) struct DirWalker {
+    left_base: PathBuf,
+    right_base: PathBuf,
+    //the common filter followed by the filter of the side
+    left_filters: Vec<FilterSet>,
+    right_filters: Vec<FilterSet>,
+    options: Options,
+    stack: Vec<Frame>,
+    //device ids of the roots, only known when staying on one file system
+    left_device: Option<u64>,
+    right_device: Option<u64>,
+}
+
+impl DirWalker {
+    pub(crate) fn new(
+        left_base: &Path,
+        right_base: &Path,
+        options: Options,
+    ) -> Result<Self, Error> {
+        let left_filters = [options.filter.clone(), options.left_filter.clone()];
+        let right_filters = [options.filter.clone(), options.right_filter.clone()];
+        let (left_device, right_device) = if options.one_file_system {
+            (device_of(left_base)?, device_of(right_base)?)
+        } else {
+            (None, None)
+        };
+        let mut walker = DirWalker {
+            left_base: left_base.to_path_buf(),
+            right_base: right_base.to_path_buf(),
+            left_filters: left_filters.into_iter().flatten().collect(),
+            right_filters: right_filters.into_iter().flatten().collect(),
+            options,
+            stack: Vec::new(),
+            left_device,
+            right_device,
+        };
+        let ignores = Ignores::new(walker.options.ignore_files);
+        let root_frame = walker.zip_frame(left_base, right_base, &ignores, 1)?;
+        walker.stack.push(root_frame);
+        Ok(walker)
+    }
+
+    fn zip_frame(
+        &self,
+        left_dir: &Path,
+        right_dir: &Path,
+        parent_ignores: &Ignores,
+        depth: usize,
+    ) -> Result<Frame, Error> {
+        trace!("comparing 2 dirs");
+        let relative = left_dir.strip_prefix(&self.left_base).unwrap();
+        let ignores = parent_ignores.enter(Some(left_dir), Some(right_dir), relative)?;
+        let entries = zip_dir_entries(
+            left_dir,
+            right_dir,
+            &self.left_base,
+            &self.right_base,
+            &self.left_filters,
+            &self.right_filters,
+            &ignores,
+            &self.options.name_matching,
+        )?;
+        Ok(Frame {
+            entries: FrameEntries::Both(entries.into_iter()),
+            left_dir: self.canonical_dir(left_dir)?,
+            right_dir: self.canonical_dir(right_dir)?,
+            ignores,
+            depth,
+        })
+    }
+
+    //ignore files of the directory currently walked
+    fn ignores(&self) -> &Ignores {
+        &self.stack.last().unwrap().ignores
+    }
+
+    //depth of the entries currently walked
+    fn depth(&self) -> usize {
+        self.stack.last().unwrap().depth
+    }
+
+    //true if pairs of directories are compared by their attributes, not only their contents
+    fn compares_dir_attributes(&self) -> bool {
+        self.options.metadata_checks.is_enabled() || self.options.xattrs.is_some()
+    }
+
+    //true if the directories currently walked must not be descended into
+    fn at_max_depth(&self) -> bool {
+        self.options
+            .max_depth
+            .is_some_and(|max_depth| self.depth() >= max_depth)
+    }
+
+    fn canonical_dir(&self, dir: &Path) -> Result<Option<PathBuf>, Error> {
+        if self.options.symlinks != SymlinkMode::Follow {
+            return Ok(None);
+        }
+        fs::canonicalize(dir)
+            .map(Some)
+            .map_err(|source| Error::ReadDir {
+                path: dir.to_path_buf(),
+                source,
+            })
+    }
+
+    //true if the directory is one of the directories currently walked, i.e. a followed
+    //symlink leads back up the tree
+    fn is_cycle(&self, dir: &Path, left: bool) -> Result<bool, Error> {
+        let canonical = match self.canonical_dir(dir)? {
+            Some(canonical) => canonical,
+            None => return Ok(false),
+        };
+        Ok(self.stack.iter().any(|frame| {
+            let walked = if left {
+                &frame.left_dir
+            } else {
+                &frame.right_dir
+            };
+            walked.as_ref() == Some(&canonical)
+        }))
+    }
+
+    //true if the directory is on another device than the root of its side
+    fn is_mount_point(&self, dir: &Path, left: bool) -> Result<bool, Error> {
+        let root_device = if left {
+            self.left_device
+        } else {
+            self.right_device
+        };
+        match root_device {
+            Some(root_device) => Ok(device_of(dir)? != Some(root_device)),
+            None => Ok(false),
+        }
+    }
+
+    //true if the entry satisfies the metadata filters of its side, only stats it if needed
+    fn matches_metadata(&self, path: &Path, kind: EntryKind, left: bool) -> Result<bool, Error> {
+        let filters = if left {
+            &self.left_filters
+        } else {
+            &self.right_filters
+        };
+        let mut predicates = filters
+            .iter()
+            .filter_map(|filter| filter.metadata.as_ref())
+            .peekable();
+        if kind == EntryKind::Dir || predicates.peek().is_none() {
+            return Ok(true);
+        }
+        //followed symlinks are judged by their target, dangling ones by the symlink itself
+        let metadata = match self.options.symlinks {
+            SymlinkMode::Follow => fs::metadata(path).or_else(|_| fs::symlink_metadata(path)),
+            _ => fs::symlink_metadata(path),
+        }
+        .map_err(read_error(path))?;
+        Ok(predicates.all(|predicate| predicate.matches(kind, &metadata)))
+    }
+
+    //returns the entry to emit, if any, and pushes sub directories onto the stack
+    fn visit_both(
+        &mut self,
+        left_entry: PathBuf,
+        right_entry: PathBuf,
+    ) -> Result<Option<WalkEntry>, Error> {
+        let mode = self.options.symlinks;
+        let (left_kind, left_symlink) = classify_entry(&left_entry, mode)?;
+        let (right_kind, right_symlink) = classify_entry(&right_entry, mode)?;
+        //pairs are compared if either side satisfies its metadata filters
+        if !self.matches_metadata(&left_entry, left_kind, true)?
+            && !self.matches_metadata(&right_entry, right_kind, false)?
+        {
+            return Ok(None);
+        }
+
+        match (left_kind, right_kind) {
+            //handle two files
+            (EntryKind::File, EntryKind::File) => Ok(Some(WalkEntry::Files {
+                left: left_entry,
+                right: right_entry,
+                left_symlink,
+                right_symlink,
+            })),
+            //handle two dirs
+            (EntryKind::Dir, EntryKind::Dir) => {
+                if !self.options.recursive {
+                    return Ok(Some(WalkEntry::Dirs {
+                        left: left_entry,
+                        right: right_entry,
+                        left_symlink,
+                        right_symlink,
+                        descended: false,
+                    }));
+                }
+                //the pair is not compared at all if either side is a mount point, only
+                //walking the other side would report its contents as one sided
+                let reason = if self.is_mount_point(&left_entry, true)?
+                    || self.is_mount_point(&right_entry, false)?
+                {
+                    Some(Unexplored::MountPoint)
+                } else if self.at_max_depth() {
+                    Some(Unexplored::DepthLimit)
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    return Ok(Some(WalkEntry::Unexplored {
+                        entry: ZipEntry::Both(left_entry, right_entry),
+                        reason,
+                        left_symlink,
+                        right_symlink,
+                    }));
+                }
+                match (
+                    self.is_cycle(&left_entry, true)?,
+                    self.is_cycle(&right_entry, false)?,
+                ) {
+                    (false, false) => {
+                        let frame = self.zip_frame(
+                            &left_entry,
+                            &right_entry,
+                            self.ignores(),
+                            self.depth() + 1,
+                        )?;
+                        self.stack.push(frame);
+                        //the pair itself is yielded before its contents if it may be reported,
+                        //a name matched despite its spelling is reported once for the whole tree
+                        if !self.compares_dir_attributes()
+                            && left_entry.file_name() == right_entry.file_name()
+                        {
+                            return Ok(None);
+                        }
+                        Ok(Some(WalkEntry::Dirs {
+                            left: left_entry,
+                            right: right_entry,
+                            left_symlink,
+                            right_symlink,
+                            descended: true,
+                        }))
+                    }
+                    (true, true) => {
+                        warn!("not following symlink cycle at {:?}", left_entry);
+                        Ok(Some(WalkEntry::Unexplored {
+                            entry: ZipEntry::Both(left_entry, right_entry),
+                            reason: Unexplored::SymlinkCycle,
+                            left_symlink,
+                            right_symlink,
+                        }))
+                    }
+                    //the side without a cycle is walked as existing on that side only
+                    (true, false) => {
+                        warn!("not following symlink cycle at {:?}", left_entry);
+                        if !self.options.ignore_right_only {
+                            self.push_right_frame(&right_entry)?;
+                        }
+                        if self.options.ignore_left_only {
+                            return Ok(None);
+                        }
+                        Ok(Some(WalkEntry::Unexplored {
+                            entry: ZipEntry::Left(left_entry),
+                            reason: Unexplored::SymlinkCycle,
+                            left_symlink,
+                            right_symlink: None,
+                        }))
+                    }
+                    (false, true) => {
+                        warn!("not following symlink cycle at {:?}", right_entry);
+                        if !self.options.ignore_left_only {
+                            self.push_left_frame(&left_entry)?;
+                        }
+                        if self.options.ignore_right_only {
+                            return Ok(None);
+                        }
+                        Ok(Some(WalkEntry::Unexplored {
+                            entry: ZipEntry::Right(right_entry),
+                            reason: Unexplored::SymlinkCycle,
+                            left_symlink: None,
+                            right_symlink,
+                        }))
+                    }
+                }
+            }
+            //ignore symlinks
+            (EntryKind::Symlink, _) | (_, EntryKind::Symlink) if mode == SymlinkMode::Ignore => {
+                Ok(None)
+            }
+            //handle two symlinks
+            (EntryKind::Symlink, EntryKind::Symlink) => Ok(Some(WalkEntry::Symlinks {
+                left: left_entry,
+                right: right_entry,
+                left_symlink: left_symlink.unwrap(),
+                right_symlink: right_symlink.unwrap(),
+            })),
+            //handle two special files
+            _ if left_kind == right_kind && is_special(left_kind) => {
+                Ok(Some(WalkEntry::Specials {
+                    left: left_entry,
+                    right: right_entry,
+                    kind: left_kind,
+                }))
+            }
+            //ignore other entries of the same kind
+            _ if left_kind == right_kind => Ok(None),
+            _ => Ok(Some(WalkEntry::TypeMismatch {
+                left: left_entry,
+                right: right_entry,
+                left_kind,
+                right_kind,
+            })),
+        }
+    }
+
+    //pushes the frame of a directory which is walked on the left side only
+    fn push_left_frame(&mut self, left_entry: &Path) -> Result<(), Error> {
+        //get elements from sub dirs
+        let relative = left_entry.strip_prefix(&self.left_base).unwrap();
+        let ignores = self.ignores().enter(Some(left_entry), None, relative)?;
+        let entries = filter_entries(
+            read_dir_paths(left_entry)?,
+            &self.left_base,
+            &self.left_filters,
+            &ignores,
+        );
+        self.stack.push(Frame {
+            entries: FrameEntries::Left(entries.into_iter()),
+            left_dir: self.canonical_dir(left_entry)?,
+            right_dir: None,
+            ignores,
+            depth: self.depth() + 1,
+        });
+        Ok(())
+    }
+
+    //pushes the frame of a directory which is walked on the right side only
+    fn push_right_frame(&mut self, right_entry: &Path) -> Result<(), Error> {
+        //get elements from sub dirs
+        let relative = right_entry.strip_prefix(&self.right_base).unwrap();
+        let ignores = self.ignores().enter(None, Some(right_entry), relative)?;
+        let entries = filter_entries(
+            read_dir_paths(right_entry)?,
+            &self.right_base,
+            &self.right_filters,
+            &ignores,
+        );
+        self.stack.push(Frame {
+            entries: FrameEntries::Right(entries.into_iter()),
+            left_dir: None,
+            right_dir: self.canonical_dir(right_entry)?,
+            ignores,
+            depth: self.depth() + 1,
+        });
+        Ok(())
+    }
+
+    fn visit_left(&mut self, left_entry: PathBuf) -> Result<Option<WalkEntry>, Error> {
+        let (kind, left_symlink) = classify_entry(&left_entry, self.options.symlinks)?;
+        if !self.matches_metadata(&left_entry, kind, true)? {
+            return Ok(None);
+        }
+        match kind {
+            EntryKind::Dir => {
+                let reason = if self.is_mount_point(&left_entry, true)? {
+                    Some(Unexplored::MountPoint)
+                } else if self.at_max_depth() {
+                    Some(Unexplored::DepthLimit)
+                } else if self.is_cycle(&left_entry, true)? {
+                    warn!("not following symlink cycle at {:?}", left_entry);
+                    Some(Unexplored::SymlinkCycle)
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    return Ok(Some(WalkEntry::Unexplored {
+                        entry: ZipEntry::Left(left_entry),
+                        reason,
+                        left_symlink,
+                        right_symlink: None,
+                    }));
+                }
+                self.push_left_frame(&left_entry)?;
+                Ok(None)
+            }
+            EntryKind::File => Ok(Some(WalkEntry::Left {
+                entry: left_entry,
+                kind,
+                symlink: left_symlink,
+            })),
+            kind if is_special(kind) => Ok(Some(WalkEntry::Left {
+                entry: left_entry,
+                kind,
+                symlink: left_symlink,
+            })),
+            EntryKind::Symlink if self.options.symlinks != SymlinkMode::Ignore => {
+                Ok(Some(WalkEntry::Left {
+                    entry: left_entry,
+                    kind,
+                    symlink: left_symlink,
+                }))
+            }
+            //ignore symlinks
+            _ => Ok(None),
+        }
+    }
+
+    fn visit_right(&mut self, right_entry: PathBuf) -> Result<Option<WalkEntry>, Error> {
+        let (kind, right_symlink) = classify_entry(&right_entry, self.options.symlinks)?;
+        if !self.matches_metadata(&right_entry, kind, false)? {
+            return Ok(None);
+        }
+        match kind {
+            EntryKind::Dir => {
+                let reason = if self.is_mount_point(&right_entry, false)? {
+                    Some(Unexplored::MountPoint)
+                } else if self.at_max_depth() {
+                    Some(Unexplored::DepthLimit)
+                } else if self.is_cycle(&right_entry, false)? {
+                    warn!("not following symlink cycle at {:?}", right_entry);
+                    Some(Unexplored::SymlinkCycle)
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    return Ok(Some(WalkEntry::Unexplored {
+                        entry: ZipEntry::Right(right_entry),
+                        reason,
+                        left_symlink: None,
+                        right_symlink,
+                    }));
+                }
+                self.push_right_frame(&right_entry)?;
+                Ok(None)
+            }
+            EntryKind::File => Ok(Some(WalkEntry::Right {
+                entry: right_entry,
+                kind,
+                symlink: right_symlink,
+            })),
+            kind if is_special(kind) => Ok(Some(WalkEntry::Right {
+                entry: right_entry,
+                kind,
+                symlink: right_symlink,
+            })),
+            EntryKind::Symlink if self.options.symlinks != SymlinkMode::Ignore => {
+                Ok(Some(WalkEntry::Right {
+                    entry: right_entry,
+                    kind,
+                    symlink: right_symlink,
+                }))
+            }
+            //ignore symlinks
+            _ => Ok(None),
+        }
+    }
+}
+
+//device id of the file system the directory is on
+#[cfg(unix)]
+fn device_of(dir: &Path) -> Result<Option<u64>, Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(dir).map_err(read_error(dir))?;
+    Ok(Some(metadata.dev()))
+}
+
+//without device ids every directory is assumed to be on the same file system
+#[cfg(not(unix))]
+fn device_of(_dir: &Path) -> Result<Option<u64>, Error> {
+    Ok(None)
+}
+
+impl Iterator for DirWalker {
+    type Item = Result<WalkEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let depth = self.stack.last()?.depth;
+            let next_entry = match &mut self.stack.last_mut()?.entries {
+                FrameEntries::Both(entries) => entries.next(),
+                FrameEntries::Left(entries) => entries.next().map(ZipEntry::Left),
+                FrameEntries::Right(entries) => entries.next().map(ZipEntry::Right),
+            };
+
+            let visited = match next_entry {
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+                //only the roots themselves are within a maximal depth of 0
+                Some(_) if self.options.max_depth == Some(0) => continue,
+                Some(ZipEntry::Both(left_entry, right_entry)) => {
+                    self.visit_both(left_entry, right_entry)
+                }
+                Some(ZipEntry::Left(left_entry)) => {
+                    if self.options.ignore_left_only {
+                        continue;
+                    }
+                    self.visit_left(left_entry)
+                }
+                Some(ZipEntry::Right(right_entry)) => {
+                    if self.options.ignore_right_only {
+                        continue;
+                    }
+                    self.visit_right(right_entry)
+                }
+            };
+
+            match visited {
+                //entries above the minimal depth are walked, but not reported
+                Ok(Some(_)) if depth < self.options.min_depth => continue,
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
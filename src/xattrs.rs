@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+use crate::Error;
+
+/// Extended attributes compared in addition to the checks of the `CompareMode`.
+///
+/// Attributes are selected by their namespace, the part of the name before the first dot,
+/// e.g. `security` or `user`. POSIX ACLs are stored in the `system` namespace on Linux.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct XattrChecks {
+    /// namespaces to compare, all namespaces if empty
+    pub include: Vec<String>,
+    /// namespaces which are never compared, even if included
+    pub exclude: Vec<String>,
+}
+
+impl XattrChecks {
+    fn is_selected(&self, name: &OsStr) -> bool {
+        let name = name.as_encoded_bytes();
+        let namespace = match name.iter().position(|byte| *byte == b'.') {
+            Some(end) => &name[..end],
+            None => name,
+        };
+        let matches = |namespaces: &Vec<String>| {
+            namespaces
+                .iter()
+                .any(|selected| selected.as_bytes() == namespace)
+        };
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+/// An extended attribute which differs between two files.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum XattrDiff {
+    /// the attribute only exists on the right
+    Added { name: OsString, value: Vec<u8> },
+    /// the attribute only exists on the left
+    Removed { name: OsString, value: Vec<u8> },
+    /// the attribute has different values
+    Changed {
+        name: OsString,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    },
+}
+
+//returns the differing attributes sorted by name
+pub(crate) fn xattr_diffs(
+    checks: &XattrChecks,
+    left_path: &Path,
+    right_path: &Path,
+) -> Result<Vec<XattrDiff>, Error> {
+    let mut left_xattrs = read_xattrs(checks, left_path)?;
+    let right_xattrs = read_xattrs(checks, right_path)?;
+    let mut diffs = Vec::new();
+
+    for (name, right_value) in right_xattrs {
+        match left_xattrs.remove(&name) {
+            None => diffs.push(XattrDiff::Added {
+                name,
+                value: right_value,
+            }),
+            Some(left_value) if left_value != right_value => diffs.push(XattrDiff::Changed {
+                name,
+                left: left_value,
+                right: right_value,
+            }),
+            Some(_) => {}
+        }
+    }
+    diffs.extend(
+        left_xattrs
+            .into_iter()
+            .map(|(name, value)| XattrDiff::Removed { name, value }),
+    );
+    diffs.sort_by(|a, b| diff_name(a).cmp(diff_name(b)));
+
+    Ok(diffs)
+}
+
+fn diff_name(diff: &XattrDiff) -> &OsStr {
+    match diff {
+        XattrDiff::Added { name, .. }
+        | XattrDiff::Removed { name, .. }
+        | XattrDiff::Changed { name, .. } => name,
+    }
+}
+
+//symlinks are followed, as files are only compared through followed symlinks
+#[cfg(unix)]
+fn read_xattrs(checks: &XattrChecks, path: &Path) -> Result<BTreeMap<OsString, Vec<u8>>, Error> {
+    use std::io;
+
+    let read_file_error = |source| Error::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let names = match xattr::list_deref(path) {
+        Ok(names) => names,
+        //file systems without extended attributes
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => return Ok(BTreeMap::new()),
+        Err(err) => return Err(read_file_error(err)),
+    };
+
+    let mut xattrs = BTreeMap::new();
+    for name in names.filter(|name| checks.is_selected(name)) {
+        //the attribute may have been removed since listing it
+        if let Some(value) = xattr::get_deref(path, &name).map_err(read_file_error)? {
+            xattrs.insert(name, value);
+        }
+    }
+    Ok(xattrs)
+}
+
+#[cfg(not(unix))]
+fn read_xattrs(_checks: &XattrChecks, _path: &Path) -> Result<BTreeMap<OsString, Vec<u8>>, Error> {
+    Ok(BTreeMap::new())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests_xattr_diffs {
+    use super::*;
+    use std::fs;
+
+    fn create_temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("xattr_diffs_")
+            .tempdir()
+            .unwrap()
+    }
+
+    #[test]
+    fn namespaces() {
+        let checks = XattrChecks {
+            include: vec!["user".to_owned(), "security".to_owned()],
+            exclude: vec!["security".to_owned()],
+        };
+        assert!(checks.is_selected(OsStr::new("user.comment")));
+        assert!(!checks.is_selected(OsStr::new("security.capability")));
+        assert!(!checks.is_selected(OsStr::new("trusted.overlay")));
+        assert!(!checks.is_selected(OsStr::new("username")));
+        assert!(XattrChecks::default().is_selected(OsStr::new("system.posix_acl_access")));
+    }
+
+    #[test]
+    fn added_removed_changed() {
+        let dir = create_temp_dir();
+        let left_file = dir.path().join("left");
+        fs::write(left_file.as_path(), b"Hello, world!").unwrap();
+        let right_file = dir.path().join("right");
+        fs::write(right_file.as_path(), b"Hello, world!").unwrap();
+
+        //not every file system used for temporary files supports user attributes
+        if xattr::set(&left_file, "user.changed", b"left").is_err() {
+            return;
+        }
+        xattr::set(&left_file, "user.removed", b"gone").unwrap();
+        xattr::set(&left_file, "user.same", b"same").unwrap();
+        xattr::set(&right_file, "user.added", b"new").unwrap();
+        xattr::set(&right_file, "user.changed", b"right").unwrap();
+        xattr::set(&right_file, "user.same", b"same").unwrap();
+
+        let diffs = xattr_diffs(&XattrChecks::default(), &left_file, &right_file).unwrap();
+        assert_eq!(
+            diffs,
+            vec![
+                XattrDiff::Added {
+                    name: "user.added".into(),
+                    value: b"new".to_vec(),
+                },
+                XattrDiff::Changed {
+                    name: "user.changed".into(),
+                    left: b"left".to_vec(),
+                    right: b"right".to_vec(),
+                },
+                XattrDiff::Removed {
+                    name: "user.removed".into(),
+                    value: b"gone".to_vec(),
+                },
+            ]
+        );
+
+        let checks = XattrChecks {
+            exclude: vec!["user".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(
+            xattr_diffs(&checks, &left_file, &right_file).unwrap(),
+            Vec::new()
+        );
+    }
+}